@@ -0,0 +1,728 @@
+use crate::utils::sanitize_table_name;
+use crate::Error;
+use apache_avro::schema_compatibility::SchemaCompatibility;
+use apache_avro::types::Value;
+use apache_avro::{to_avro_datum, Codec, Decimal, Reader, Schema as AvroSchema};
+use comfy_table::{Cell, Table};
+use datafusion::arrow::array::{new_empty_array, Array, ArrayRef};
+use datafusion::arrow::compute::cast;
+use datafusion::arrow::datatypes::{i256, DataType, Field, Fields, Schema, SchemaRef, TimeUnit};
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::common::ScalarValue;
+use serde_json::{json, Value as JsonValue};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Number of records read into each record batch
+const READ_BATCH_SIZE: usize = 8192;
+
+/// Options for writing Avro files
+#[derive(Debug, Clone)]
+pub struct AvroWriterOptions {
+    /// Compression codec for data blocks
+    pub codec: Codec,
+    /// Avro schema file to use instead of deriving the schema from the Arrow schema
+    pub schema: Option<PathBuf>,
+}
+
+impl Default for AvroWriterOptions {
+    fn default() -> Self {
+        Self {
+            codec: Codec::Null,
+            schema: None,
+        }
+    }
+}
+
+impl AvroWriterOptions {
+    /// The Avro schema to write, either loaded from the schema file or derived from the Arrow
+    /// schema
+    pub fn avro_schema(&self, schema: &Schema) -> Result<AvroSchema, Error> {
+        match &self.schema {
+            Some(schema_path) => Ok(AvroSchema::parse_str(&std::fs::read_to_string(
+                schema_path,
+            )?)?),
+            None => arrow_to_avro_schema(schema),
+        }
+    }
+}
+
+/// Parse an Avro codec name (`null`, `deflate`, `snappy`, or `zstd`)
+pub fn parse_avro_codec(codec: &str) -> Result<Codec, Error> {
+    match codec.to_lowercase().as_str() {
+        "zstd" => Ok(Codec::Zstandard),
+        other => Codec::from_str(other)
+            .map_err(|_| Error::General(format!("Unsupported Avro codec '{}'", codec))),
+    }
+}
+
+/// Write the header of an Avro container file that has no data blocks. apache-avro only writes
+/// the header along with the first record, which would leave a file without rows empty.
+pub(crate) fn write_avro_header(
+    writer: &mut impl Write,
+    schema: &AvroSchema,
+    codec: Codec,
+) -> Result<(), Error> {
+    let codec: &str = codec.into();
+    let metadata = HashMap::from([
+        (
+            "avro.schema".to_string(),
+            Value::Bytes(serde_json::to_string(schema)?.into_bytes()),
+        ),
+        (
+            "avro.codec".to_string(),
+            Value::Bytes(codec.as_bytes().to_vec()),
+        ),
+    ]);
+    let metadata_schema = AvroSchema::Map(Box::new(AvroSchema::Bytes));
+    writer.write_all(b"Obj\x01")?;
+    writer.write_all(&to_avro_datum(&metadata_schema, Value::Map(metadata))?)?;
+    // the sync marker follows each data block, so its value does not matter without any
+    writer.write_all(&[0_u8; 16])?;
+    Ok(())
+}
+
+/// Derive an Avro schema from an Arrow schema, using logical types for decimals, dates, times
+/// and timestamps. Nullable fields are written as a union with `null`.
+pub fn arrow_to_avro_schema(schema: &Schema) -> Result<AvroSchema, Error> {
+    let fields = avro_fields(schema.fields(), "record")?;
+    let json = json!({"type": "record", "name": "record", "fields": fields});
+    AvroSchema::parse(&json).map_err(Error::from)
+}
+
+fn avro_fields(fields: &Fields, parent: &str) -> Result<Vec<JsonValue>, Error> {
+    fields
+        .iter()
+        .zip(avro_names(fields)?)
+        .map(|(field, name)| avro_field(field, name, parent))
+        .collect()
+}
+
+fn avro_field(field: &Field, name: String, parent: &str) -> Result<JsonValue, Error> {
+    let data_type = avro_type(field.data_type(), &format!("{}_{}", parent, name))?;
+    Ok(
+        if field.is_nullable() && field.data_type() != &DataType::Null {
+            json!({"name": name, "type": ["null", data_type], "default": null})
+        } else {
+            json!({"name": name, "type": data_type})
+        },
+    )
+}
+
+/// Avro names must start with a letter or underscore and contain only letters, digits and
+/// underscores
+fn avro_name(name: &str) -> String {
+    let name = sanitize_table_name(name);
+    if name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        name
+    } else {
+        format!("_{}", name)
+    }
+}
+
+/// The Avro names of a list of fields, failing if two fields have the same name once sanitized,
+/// such as `a-b` and `a_b`
+fn avro_names(fields: &Fields) -> Result<Vec<String>, Error> {
+    let names: Vec<String> = fields.iter().map(|f| avro_name(f.name())).collect();
+    let mut seen: HashMap<&str, usize> = HashMap::new();
+    for (i, name) in names.iter().enumerate() {
+        if let Some(j) = seen.insert(name, i) {
+            return Err(Error::General(format!(
+                "Columns '{}' and '{}' would both be written as '{}' in Avro",
+                fields[j].name(),
+                fields[i].name(),
+                name
+            )));
+        }
+    }
+    Ok(names)
+}
+
+/// Map an Arrow type to an Avro type. The name is used for named types such as records and
+/// fixed, which must be unique within the schema.
+fn avro_type(data_type: &DataType, name: &str) -> Result<JsonValue, Error> {
+    Ok(match data_type {
+        DataType::Null => json!("null"),
+        DataType::Boolean => json!("boolean"),
+        DataType::Int8 | DataType::Int16 | DataType::Int32 => json!("int"),
+        DataType::UInt8 | DataType::UInt16 => json!("int"),
+        DataType::Int64 | DataType::UInt32 | DataType::UInt64 => json!("long"),
+        DataType::Float16 | DataType::Float32 => json!("float"),
+        DataType::Float64 => json!("double"),
+        DataType::Utf8 | DataType::LargeUtf8 => json!("string"),
+        DataType::Binary | DataType::LargeBinary => json!("bytes"),
+        DataType::FixedSizeBinary(size) => json!({"type": "fixed", "name": name, "size": size}),
+        DataType::Decimal128(precision, scale) | DataType::Decimal256(precision, scale) => {
+            json!({"type": "bytes", "logicalType": "decimal", "precision": precision, "scale": scale})
+        }
+        DataType::Date32 | DataType::Date64 => json!({"type": "int", "logicalType": "date"}),
+        DataType::Time32(_) => json!({"type": "int", "logicalType": "time-millis"}),
+        DataType::Time64(_) => json!({"type": "long", "logicalType": "time-micros"}),
+        DataType::Timestamp(TimeUnit::Second | TimeUnit::Millisecond, _) => {
+            json!({"type": "long", "logicalType": "timestamp-millis"})
+        }
+        DataType::Timestamp(TimeUnit::Microsecond | TimeUnit::Nanosecond, _) => {
+            json!({"type": "long", "logicalType": "timestamp-micros"})
+        }
+        DataType::List(field) | DataType::LargeList(field) | DataType::FixedSizeList(field, _) => {
+            let items = avro_type(field.data_type(), name)?;
+            let items = if field.is_nullable() && field.data_type() != &DataType::Null {
+                json!(["null", items])
+            } else {
+                items
+            };
+            json!({"type": "array", "items": items})
+        }
+        DataType::Struct(fields) => {
+            let fields = avro_fields(fields, name)?;
+            json!({"type": "record", "name": name, "fields": fields})
+        }
+        DataType::Dictionary(_, value_type) => avro_type(value_type, name)?,
+        other => {
+            return Err(Error::General(format!(
+                "Conversion of {} to Avro is not supported",
+                other
+            )))
+        }
+    })
+}
+
+/// Convert the rows of a record batch to Avro records
+pub(crate) fn batch_to_avro(batch: &RecordBatch) -> Result<Vec<Value>, Error> {
+    let schema = batch.schema();
+    let names = avro_names(schema.fields())?;
+    (0..batch.num_rows())
+        .map(|row| {
+            let fields = names
+                .iter()
+                .zip(batch.columns())
+                .map(|(name, array)| Ok((name.clone(), array_value(array, row)?)))
+                .collect::<Result<Vec<_>, Error>>()?;
+            Ok(Value::Record(fields))
+        })
+        .collect()
+}
+
+fn array_value(array: &ArrayRef, row: usize) -> Result<Value, Error> {
+    if array.is_null(row) {
+        return Ok(Value::Null);
+    }
+    scalar_to_avro(ScalarValue::try_from_array(array, row)?)
+}
+
+fn list_to_avro(array: ArrayRef) -> Result<Value, Error> {
+    let values = (0..array.len())
+        .map(|i| array_value(&array, i))
+        .collect::<Result<Vec<_>, Error>>()?;
+    Ok(Value::Array(values))
+}
+
+fn scalar_to_avro(value: ScalarValue) -> Result<Value, Error> {
+    Ok(match value {
+        ScalarValue::Null => Value::Null,
+        ScalarValue::Boolean(Some(v)) => Value::Boolean(v),
+        ScalarValue::Int8(Some(v)) => Value::Int(v as i32),
+        ScalarValue::Int16(Some(v)) => Value::Int(v as i32),
+        ScalarValue::Int32(Some(v)) => Value::Int(v),
+        ScalarValue::Int64(Some(v)) => Value::Long(v),
+        ScalarValue::UInt8(Some(v)) => Value::Int(v as i32),
+        ScalarValue::UInt16(Some(v)) => Value::Int(v as i32),
+        ScalarValue::UInt32(Some(v)) => Value::Long(v as i64),
+        ScalarValue::UInt64(Some(v)) => Value::Long(i64::try_from(v).map_err(|_| {
+            Error::General(format!("UInt64 value {} is too large for an Avro long", v))
+        })?),
+        ScalarValue::Float32(Some(v)) => Value::Float(v),
+        ScalarValue::Float64(Some(v)) => Value::Double(v),
+        ScalarValue::Utf8(Some(v)) | ScalarValue::LargeUtf8(Some(v)) => Value::String(v),
+        ScalarValue::Binary(Some(v)) | ScalarValue::LargeBinary(Some(v)) => Value::Bytes(v),
+        ScalarValue::FixedSizeBinary(size, Some(v)) => Value::Fixed(size as usize, v),
+        ScalarValue::Decimal128(Some(v), _, _) => Value::Decimal(Decimal::from(v.to_be_bytes())),
+        ScalarValue::Decimal256(Some(v), _, _) => Value::Decimal(Decimal::from(v.to_be_bytes())),
+        ScalarValue::Date32(Some(v)) => Value::Date(v),
+        ScalarValue::Date64(Some(v)) => Value::Date((v / 86_400_000) as i32),
+        ScalarValue::Time32Second(Some(v)) => Value::TimeMillis(v * 1000),
+        ScalarValue::Time32Millisecond(Some(v)) => Value::TimeMillis(v),
+        ScalarValue::Time64Microsecond(Some(v)) => Value::TimeMicros(v),
+        ScalarValue::Time64Nanosecond(Some(v)) => Value::TimeMicros(v / 1000),
+        ScalarValue::TimestampSecond(Some(v), _) => Value::TimestampMillis(v * 1000),
+        ScalarValue::TimestampMillisecond(Some(v), _) => Value::TimestampMillis(v),
+        ScalarValue::TimestampMicrosecond(Some(v), _) => Value::TimestampMicros(v),
+        ScalarValue::TimestampNanosecond(Some(v), _) => Value::TimestampMicros(v / 1000),
+        ScalarValue::List(array) => list_to_avro(array.value(0))?,
+        ScalarValue::LargeList(array) => list_to_avro(array.value(0))?,
+        ScalarValue::FixedSizeList(array) => list_to_avro(array.value(0))?,
+        ScalarValue::Struct(Some(values), fields) => Value::Record(
+            avro_names(&fields)?
+                .into_iter()
+                .zip(values)
+                .map(|(name, v)| Ok((name, scalar_to_avro(v)?)))
+                .collect::<Result<Vec<_>, Error>>()?,
+        ),
+        ScalarValue::Dictionary(_, value) => scalar_to_avro(*value)?,
+        other if other.is_null() => Value::Null,
+        other => {
+            return Err(Error::General(format!(
+                "Conversion of {} to Avro is not supported",
+                other.data_type()
+            )))
+        }
+    })
+}
+
+/// Read the records of an Avro container file into record batches with the Arrow schema the
+/// file was written from, mapping each Avro value back to the Arrow type that produced it. This
+/// covers types such as decimals that the Avro reader in DataFusion does not support.
+pub fn read_avro_batches(path: &Path, schema: SchemaRef) -> Result<Vec<RecordBatch>, Error> {
+    let reader = Reader::new(BufReader::new(File::open(path)?))?;
+    let mut batches = vec![];
+    let mut columns: Vec<Vec<ScalarValue>> = vec![vec![]; schema.fields().len()];
+    let mut rows = 0;
+    for record in reader {
+        let fields = match record? {
+            Value::Record(fields) if fields.len() == columns.len() => fields,
+            _ => {
+                return Err(Error::General(format!(
+                    "Avro record does not match the schema {}",
+                    schema
+                )))
+            }
+        };
+        for ((_, value), (column, field)) in fields
+            .into_iter()
+            .zip(columns.iter_mut().zip(schema.fields()))
+        {
+            column.push(avro_to_scalar(value, field.data_type())?);
+        }
+        rows += 1;
+        if rows % READ_BATCH_SIZE == 0 {
+            batches.push(scalars_to_batch(&mut columns, &schema)?);
+        }
+    }
+    if rows % READ_BATCH_SIZE != 0 || rows == 0 {
+        batches.push(scalars_to_batch(&mut columns, &schema)?);
+    }
+    Ok(batches)
+}
+
+fn scalars_to_batch(
+    columns: &mut [Vec<ScalarValue>],
+    schema: &SchemaRef,
+) -> Result<RecordBatch, Error> {
+    let arrays = columns
+        .iter_mut()
+        .zip(schema.fields())
+        .map(|(values, field)| match values.is_empty() {
+            true => Ok(new_empty_array(field.data_type())),
+            false => ScalarValue::iter_to_array(values.drain(..)),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(RecordBatch::try_new(schema.clone(), arrays)?)
+}
+
+/// Convert an Avro value to a scalar of the given Arrow type, reversing `scalar_to_avro`
+fn avro_to_scalar(value: Value, data_type: &DataType) -> Result<ScalarValue, Error> {
+    let scalar = match value {
+        Value::Null => return Ok(ScalarValue::try_from(data_type)?),
+        Value::Union(_, value) => return avro_to_scalar(*value, data_type),
+        Value::Boolean(v) => ScalarValue::Boolean(Some(v)),
+        Value::Int(v) => ScalarValue::Int32(Some(v)),
+        Value::Long(v) => ScalarValue::Int64(Some(v)),
+        Value::Float(v) => ScalarValue::Float32(Some(v)),
+        Value::Double(v) => ScalarValue::Float64(Some(v)),
+        Value::String(v) | Value::Enum(_, v) => ScalarValue::Utf8(Some(v)),
+        Value::Uuid(v) => ScalarValue::Utf8(Some(v.to_string())),
+        Value::Bytes(v) => ScalarValue::Binary(Some(v)),
+        Value::Fixed(size, v) => ScalarValue::FixedSizeBinary(size as i32, Some(v)),
+        Value::Decimal(v) => {
+            let bytes = Vec::<u8>::try_from(&v)?;
+            match data_type {
+                DataType::Decimal128(precision, scale) => ScalarValue::Decimal128(
+                    Some(i128::from_be_bytes(sign_extend(&bytes)?)),
+                    *precision,
+                    *scale,
+                ),
+                DataType::Decimal256(precision, scale) => ScalarValue::Decimal256(
+                    Some(i256::from_be_bytes(sign_extend(&bytes)?)),
+                    *precision,
+                    *scale,
+                ),
+                other => {
+                    return Err(Error::General(format!(
+                        "Cannot read an Avro decimal as {}",
+                        other
+                    )))
+                }
+            }
+        }
+        Value::Date(v) => ScalarValue::Date32(Some(v)),
+        Value::TimeMillis(v) => ScalarValue::Time32Millisecond(Some(v)),
+        Value::TimeMicros(v) => ScalarValue::Time64Microsecond(Some(v)),
+        // the time zone is not stored in Avro, so it is taken from the Arrow type
+        Value::TimestampMillis(v) | Value::LocalTimestampMillis(v) => {
+            ScalarValue::TimestampMillisecond(Some(v), timestamp_tz(data_type))
+        }
+        Value::TimestampMicros(v) | Value::LocalTimestampMicros(v) => {
+            ScalarValue::TimestampMicrosecond(Some(v), timestamp_tz(data_type))
+        }
+        Value::Array(values) => {
+            let item_type = match data_type {
+                DataType::List(field)
+                | DataType::LargeList(field)
+                | DataType::FixedSizeList(field, _) => field.data_type(),
+                other => {
+                    return Err(Error::General(format!(
+                        "Cannot read an Avro array as {}",
+                        other
+                    )))
+                }
+            };
+            let values = values
+                .into_iter()
+                .map(|v| avro_to_scalar(v, item_type))
+                .collect::<Result<Vec<_>, _>>()?;
+            ScalarValue::List(ScalarValue::new_list(&values, item_type))
+        }
+        Value::Record(values) => match data_type {
+            DataType::Struct(fields) if fields.len() == values.len() => ScalarValue::Struct(
+                Some(
+                    values
+                        .into_iter()
+                        .zip(fields)
+                        .map(|((_, v), field)| avro_to_scalar(v, field.data_type()))
+                        .collect::<Result<Vec<_>, _>>()?,
+                ),
+                fields.clone(),
+            ),
+            other => {
+                return Err(Error::General(format!(
+                    "Cannot read an Avro record as {}",
+                    other
+                )))
+            }
+        },
+        other => {
+            return Err(Error::General(format!(
+                "Reading Avro {:?} values is not supported",
+                other
+            )))
+        }
+    };
+    // Avro types are often wider than the Arrow type they were written from, such as a long
+    // for a UInt32 column
+    if &scalar.data_type() == data_type {
+        Ok(scalar)
+    } else {
+        let array = cast(&scalar.to_array()?, data_type)?;
+        Ok(ScalarValue::try_from_array(&array, 0)?)
+    }
+}
+
+fn timestamp_tz(data_type: &DataType) -> Option<Arc<str>> {
+    match data_type {
+        DataType::Timestamp(_, tz) => tz.clone(),
+        _ => None,
+    }
+}
+
+/// Sign-extend the big-endian two's complement bytes of an Avro decimal to a fixed width
+fn sign_extend<const N: usize>(bytes: &[u8]) -> Result<[u8; N], Error> {
+    if bytes.len() > N {
+        return Err(Error::General(format!(
+            "Avro decimal of {} bytes does not fit in {} bytes",
+            bytes.len(),
+            N
+        )));
+    }
+    let negative = bytes.first().is_some_and(|b| b & 0x80 != 0);
+    let mut extended = [if negative { 0xFF } else { 0 }; N];
+    extended[N - bytes.len()..].copy_from_slice(bytes);
+    Ok(extended)
+}
+
+/// Print the header and block layout of an Avro container file. When a reader schema is
+/// provided, also check whether the file can be read with that schema.
+pub fn view_avro_meta(path: PathBuf, reader_schema: Option<PathBuf>) -> Result<(), Error> {
+    let mut reader = BufReader::new(File::open(&path)?);
+    let mut magic = [0_u8; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != b"Obj\x01" {
+        return Err(Error::General(format!(
+            "{} is not an Avro container file",
+            path.display()
+        )));
+    }
+    let metadata = read_metadata(&mut reader)?;
+    let mut sync = [0_u8; 16];
+    reader.read_exact(&mut sync)?;
+
+    let writer_schema_json = metadata
+        .iter()
+        .find(|(k, _)| k == "avro.schema")
+        .map(|(_, v)| String::from_utf8_lossy(v).to_string())
+        .ok_or_else(|| Error::General("Avro file has no schema".to_string()))?;
+    let codec = metadata
+        .iter()
+        .find(|(k, _)| k == "avro.codec")
+        .map(|(_, v)| String::from_utf8_lossy(v).to_string())
+        .unwrap_or_else(|| "null".to_string());
+
+    let mut blocks = vec![];
+    while let Some(records) = read_long_or_eof(&mut reader)? {
+        if records < 0 {
+            return Err(Error::General(format!(
+                "Invalid negative row count {} in block {}",
+                records,
+                blocks.len()
+            )));
+        }
+        let size = read_length(&mut reader)?;
+        let skipped = std::io::copy(&mut reader.by_ref().take(size), &mut std::io::sink())?;
+        if skipped < size {
+            return Err(Error::General("Unexpected end of Avro file".to_string()));
+        }
+        let mut block_sync = [0_u8; 16];
+        reader.read_exact(&mut block_sync)?;
+        if block_sync != sync {
+            return Err(Error::General(format!(
+                "Sync marker mismatch after block {}",
+                blocks.len()
+            )));
+        }
+        blocks.push((records, size));
+    }
+
+    let mut table = Table::new();
+    table.load_preset("||--+-++|    ++++++");
+    table.set_header(vec![Cell::new("Key"), Cell::new("Value")]);
+    table.add_row(vec![Cell::new("Codec"), Cell::new(&codec)]);
+    table.add_row(vec![Cell::new("Sync Marker"), Cell::new(hex(&sync))]);
+    table.add_row(vec![
+        Cell::new("Rows"),
+        Cell::new(format!("{}", blocks.iter().map(|(n, _)| n).sum::<i64>())),
+    ]);
+    table.add_row(vec![
+        Cell::new("Blocks"),
+        Cell::new(format!("{}", blocks.len())),
+    ]);
+    for (key, value) in metadata.iter().filter(|(k, _)| !k.starts_with("avro.")) {
+        table.add_row(vec![
+            Cell::new(key),
+            Cell::new(String::from_utf8_lossy(value)),
+        ]);
+    }
+    println!("{}", table);
+
+    let writer_schema_value: JsonValue = serde_json::from_str(&writer_schema_json)?;
+    println!(
+        "\nWriter schema:\n\n{}",
+        serde_json::to_string_pretty(&writer_schema_value)?
+    );
+
+    let mut table = Table::new();
+    table.load_preset("||--+-++|    ++++++");
+    table.set_header(vec![
+        Cell::new("Block"),
+        Cell::new("Rows"),
+        Cell::new("Bytes"),
+    ]);
+    for (i, (records, size)) in blocks.iter().enumerate() {
+        table.add_row(vec![
+            Cell::new(format!("{}", i)),
+            Cell::new(format!("{}", records)),
+            Cell::new(format!("{}", size)),
+        ]);
+    }
+    println!("\n{}", table);
+
+    if let Some(reader_schema) = reader_schema {
+        let writer_schema = AvroSchema::parse_str(&writer_schema_json)?;
+        let reader_schema = AvroSchema::parse_str(&std::fs::read_to_string(reader_schema)?)?;
+        let compatible = SchemaCompatibility::can_read(&writer_schema, &reader_schema);
+        println!(
+            "\nReader schema is {}compatible with the writer schema",
+            if compatible { "" } else { "not " }
+        );
+        let file = BufReader::new(File::open(&path)?);
+        let mut rows = 0;
+        for value in Reader::with_schema(&reader_schema, file)? {
+            if let Err(e) = value {
+                return Err(Error::General(format!(
+                    "Failed to read row {} with reader schema: {}",
+                    rows, e
+                )));
+            }
+            rows += 1;
+        }
+        println!("Read {} rows with the reader schema", rows);
+    }
+    Ok(())
+}
+
+/// Read the file metadata map from an Avro container header
+fn read_metadata(reader: &mut impl Read) -> Result<Vec<(String, Vec<u8>)>, Error> {
+    let mut metadata = vec![];
+    loop {
+        let mut count = read_long(reader)?;
+        if count == 0 {
+            return Ok(metadata);
+        }
+        if count < 0 {
+            // a negative count is followed by the size of the block in bytes
+            count = count.checked_neg().ok_or_else(|| {
+                Error::General(format!("Invalid block count {} in Avro file", count))
+            })?;
+            read_long(reader)?;
+        }
+        for _ in 0..count {
+            let key = String::from_utf8_lossy(&read_bytes(reader)?).to_string();
+            let value = read_bytes(reader)?;
+            metadata.push((key, value));
+        }
+    }
+}
+
+fn read_bytes(reader: &mut impl Read) -> Result<Vec<u8>, Error> {
+    let len = read_length(reader)?;
+    // the length is not trusted for the allocation, since a corrupt file can claim any size
+    let mut bytes = vec![];
+    reader.take(len).read_to_end(&mut bytes)?;
+    if (bytes.len() as u64) < len {
+        return Err(Error::General("Unexpected end of Avro file".to_string()));
+    }
+    Ok(bytes)
+}
+
+/// Read a length or count, which must not be negative
+fn read_length(reader: &mut impl Read) -> Result<u64, Error> {
+    let value = read_long(reader)?;
+    u64::try_from(value)
+        .map_err(|_| Error::General(format!("Invalid negative length {} in Avro file", value)))
+}
+
+fn read_long(reader: &mut impl Read) -> Result<i64, Error> {
+    read_long_or_eof(reader)?
+        .ok_or_else(|| Error::General("Unexpected end of Avro file".to_string()))
+}
+
+/// Read a zig-zag encoded variable-length long, returning `None` at the end of the file
+fn read_long_or_eof(reader: &mut impl Read) -> Result<Option<i64>, Error> {
+    let mut value: u64 = 0;
+    let mut shift = 0;
+    let mut byte = [0_u8; 1];
+    loop {
+        if reader.read(&mut byte)? == 0 {
+            return if shift == 0 {
+                Ok(None)
+            } else {
+                Err(Error::General("Unexpected end of Avro file".to_string()))
+            };
+        }
+        value |= ((byte[0] & 0x7F) as u64) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(Some((value >> 1) as i64 ^ -((value & 1) as i64)));
+        }
+        shift += 7;
+        if shift > 63 {
+            return Err(Error::General(
+                "Invalid variable-length integer".to_string(),
+            ));
+        }
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode_long(value: i64) -> Vec<u8> {
+        to_avro_datum(&AvroSchema::Long, Value::Long(value)).unwrap()
+    }
+
+    fn encode_bytes(value: &[u8]) -> Vec<u8> {
+        let mut bytes = encode_long(value.len() as i64);
+        bytes.extend_from_slice(value);
+        bytes
+    }
+
+    #[test]
+    fn read_variable_length_longs() {
+        for value in [0, -1, 1, -64, 64, 1 << 40, i64::MAX, i64::MIN] {
+            let bytes = encode_long(value);
+            assert_eq!(read_long(&mut bytes.as_slice()).unwrap(), value);
+        }
+        assert_eq!(read_long(&mut [0x80, 0x01].as_slice()).unwrap(), 64);
+        assert_eq!(read_long_or_eof(&mut [].as_slice()).unwrap(), None);
+    }
+
+    #[test]
+    fn reject_invalid_longs() {
+        // the continuation bit is set on the last byte
+        assert!(read_long(&mut [0x80].as_slice()).is_err());
+        assert!(read_long(&mut [].as_slice()).is_err());
+        // more than the 10 bytes needed for 64 bits
+        assert!(read_long(&mut [0x80; 11].as_slice()).is_err());
+        assert!(read_length(&mut encode_long(-1).as_slice()).is_err());
+    }
+
+    #[test]
+    fn read_metadata_blocks() {
+        let mut bytes = encode_long(1);
+        bytes.extend(encode_bytes(b"avro.codec"));
+        bytes.extend(encode_bytes(b"null"));
+        // a negative count is followed by the size of the block
+        bytes.extend(encode_long(-1));
+        bytes.extend(encode_long(8));
+        bytes.extend(encode_bytes(b"k"));
+        bytes.extend(encode_bytes(b"v"));
+        bytes.extend(encode_long(0));
+        assert_eq!(
+            read_metadata(&mut bytes.as_slice()).unwrap(),
+            vec![
+                ("avro.codec".to_string(), b"null".to_vec()),
+                ("k".to_string(), b"v".to_vec())
+            ]
+        );
+    }
+
+    #[test]
+    fn reject_invalid_metadata() {
+        let mut min_count = encode_long(i64::MIN);
+        min_count.extend(encode_long(0));
+        let mut negative_length = encode_long(1);
+        negative_length.extend(encode_long(-5));
+        let mut truncated = encode_long(1);
+        truncated.extend(encode_long(10));
+        truncated.extend(b"short");
+        for bytes in [min_count, negative_length, truncated, encode_long(2)] {
+            assert!(read_metadata(&mut bytes.as_slice()).is_err());
+        }
+    }
+
+    #[test]
+    fn read_written_header() {
+        let schema = AvroSchema::parse_str(
+            r#"{"type": "record", "name": "t", "fields": [{"name": "a", "type": "long"}]}"#,
+        )
+        .unwrap();
+        let mut bytes = vec![];
+        write_avro_header(&mut bytes, &schema, Codec::Deflate).unwrap();
+        let mut reader = bytes.strip_prefix(b"Obj\x01").unwrap();
+        let mut metadata = read_metadata(&mut reader).unwrap();
+        metadata.sort();
+        assert_eq!(metadata[0], ("avro.codec".to_string(), b"deflate".to_vec()));
+        assert_eq!(metadata[1].0, "avro.schema");
+        let written = AvroSchema::parse_str(&String::from_utf8(metadata[1].1.clone()).unwrap());
+        assert_eq!(written.unwrap(), schema);
+        assert_eq!(reader, [0_u8; 16]);
+        // the apache-avro reader accepts the header of a file without records
+        assert_eq!(Reader::new(bytes.as_slice()).unwrap().count(), 0);
+    }
+}
@@ -12,20 +12,52 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use bdt::avro::{parse_avro_codec, view_avro_meta, AvroWriterOptions};
+use bdt::cast::{parse_column_cast, CastOptions, ColumnCast};
 use bdt::compare::ComparisonResult;
-use bdt::convert::convert_files;
-use bdt::parquet::view_parquet_meta;
-use bdt::utils::{parse_filename, register_table, sanitize_table_name};
+use bdt::convert::{
+    convert_dir, convert_files, load_metadata_file, parse_column_encoding, parse_compression,
+    parse_key_value, parse_rename, parse_sort_column, parse_statistics, parse_writer_version,
+    write_output, BatchConvertOptions, ConvertOptions, ParquetWriterOptions, SortColumn,
+};
+use bdt::csv::{
+    parse_delimiter, parse_line_ending, parse_quote_style, CsvWriterOptions, QuoteStyle,
+};
+use bdt::json::{
+    parse_binary_format, parse_decimal_format, parse_json_format, parse_timestamp_format,
+    BinaryFormat, DecimalFormat, JsonFormat, JsonWriterOptions, TimestampFormat,
+};
+use bdt::merge::merge_files;
+use bdt::parquet::{
+    parse_report_format, probe_bloom_filter, view_parquet_meta, view_parquet_pages, ReportFormat,
+};
+use bdt::partition::PartitionOptions;
+use bdt::progress::Progress;
+use bdt::rewrite::rewrite_files;
+use bdt::sniff::{view_csv_dialect, LineTerminator};
+use bdt::utils::{parse_filename, register_table, sanitize_table_name, ReaderOptions};
+use bdt::writer::{parse_size, remove_pending_outputs, FileSplitOptions};
 use bdt::{compare, Error};
 use datafusion::common::DataFusionError;
-use datafusion::dataframe::DataFrameWriteOptions;
+use datafusion::execution::disk_manager::DiskManagerConfig;
+use datafusion::execution::memory_pool::FairSpillPool;
+use datafusion::execution::runtime_env::{RuntimeConfig, RuntimeEnv};
+use datafusion::parquet::basic::{Compression, Encoding, ZstdLevel};
+use datafusion::parquet::file::properties::{EnabledStatistics, WriterVersion};
 use datafusion::prelude::*;
+use futures::future::Either;
 use std::fs;
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 use structopt::StructOpt;
 
+/// Memory available to operators that can spill to disk when no limit is given
+const DEFAULT_MEMORY_LIMIT: usize = 4 << 30;
+
 #[derive(Debug, StructOpt)]
 #[structopt(name = "bdt", about = "Boring Data Tool")]
+#[allow(clippy::large_enum_variant)]
 enum Command {
     /// View contents of a file
     View {
@@ -33,29 +65,192 @@ enum Command {
         filename: PathBuf,
         #[structopt(short, long)]
         limit: Option<usize>,
+        #[structopt(flatten)]
+        reader: ReaderArgs,
     },
     /// View schema of a file
     Schema {
         #[structopt(parse(from_os_str))]
         filename: PathBuf,
+        #[structopt(flatten)]
+        reader: ReaderArgs,
     },
     /// Convert a file to a different format
     Convert {
         /// Output in a single file
+        #[structopt(short, long, conflicts_with_all = &["max-rows-per-file", "target-file-size"])]
+        single_file: bool,
+        #[structopt(parse(from_os_str), required_unless = "input-dir")]
+        input: Option<PathBuf>,
+        #[structopt(parse(from_os_str), required_unless = "input-dir")]
+        output: Option<PathBuf>,
+        /// Convert every file in this directory and its subdirectories
+        #[structopt(
+            parse(from_os_str),
+            long,
+            conflicts_with_all = &["input", "output"],
+            requires_all = &["output-dir", "to"]
+        )]
+        input_dir: Option<PathBuf>,
+        /// Directory to write converted files to, keeping their relative paths
+        #[structopt(parse(from_os_str), long, requires = "input-dir")]
+        output_dir: Option<PathBuf>,
+        /// Output format for converting a directory, such as parquet
+        #[structopt(long, requires = "input-dir")]
+        to: Option<String>,
+        /// Only convert files whose path relative to the input directory matches this glob
+        /// pattern, such as "**/*.csv"
+        #[structopt(long, requires = "input-dir")]
+        include: Option<String>,
+        /// Maximum number of files to convert at once (defaults to the number of CPUs)
+        #[structopt(long, requires = "input-dir")]
+        jobs: Option<usize>,
+        /// Columns or SQL expressions to write, such as "a, b * 2 AS c"
+        #[structopt(long, conflicts_with = "sql")]
+        select: Option<String>,
+        /// SQL filter for the rows to write, such as "a > 10"
+        #[structopt(long = "where", conflicts_with = "sql")]
+        filter: Option<String>,
+        /// Rename a column, such as old_name=new_name. Can be repeated
+        #[structopt(long, parse(try_from_str = parse_rename), number_of_values = 1)]
+        rename: Vec<(String, String)>,
+        /// SQL query against the input table 't' that produces the rows to write
+        #[structopt(long)]
+        sql: Option<String>,
+        /// Write a directory per partition value, such as region=EU/date=2024-01-01, for a
+        /// comma-separated list of columns
+        #[structopt(
+            long,
+            use_delimiter = true,
+            number_of_values = 1,
+            conflicts_with = "single-file"
+        )]
+        partition_by: Vec<String>,
+        /// Leave the partition columns out of the data files
+        #[structopt(long, requires = "partition-by")]
+        exclude_partition_columns: bool,
+        /// Maximum number of partition files to keep open at once
+        #[structopt(long, requires = "partition-by")]
+        max_open_files: Option<usize>,
+        /// Sort the output by a comma-separated list of columns, each followed by an optional
+        /// asc|desc and nulls first|last, such as "region, date desc"
+        #[structopt(
+            long,
+            parse(try_from_str = parse_sort_column),
+            use_delimiter = true,
+            number_of_values = 1
+        )]
+        sort_by: Vec<SortColumn>,
+        /// Memory available to sorts and aggregations before they spill to temporary files,
+        /// such as 512MB [default: 4GB]
+        #[structopt(long, parse(try_from_str = parse_size))]
+        memory_limit: Option<usize>,
+        /// Cast a column to a SQL type, such as price=DECIMAL(18,4). Dates and timestamps can be
+        /// parsed with a format, such as "ts=TIMESTAMP:%d/%m/%Y %H:%M". Can be repeated
+        #[structopt(long, parse(try_from_str = parse_column_cast), number_of_values = 1)]
+        cast: Vec<ColumnCast>,
+        /// Schema file or column definitions to cast the output to, such as "id INT, ts TIMESTAMP"
+        #[structopt(long)]
+        target_schema: Option<String>,
+        /// Write null for values that cannot be cast instead of failing
+        #[structopt(long)]
+        null_on_cast_error: bool,
+        /// Read the output back after writing and fail if any values were lost or changed
+        #[structopt(long, conflicts_with = "partition-by")]
+        verify: bool,
+        /// Fail instead of warning when a column type cannot be represented exactly in the
+        /// output format
+        #[structopt(long)]
+        strict: bool,
+        /// Overwrite the output if it already exists
+        #[structopt(long)]
+        force: bool,
+        /// Do not show progress or print a summary when finished
         #[structopt(short, long)]
+        quiet: bool,
+        #[structopt(flatten)]
+        split: FileSplitArgs,
+        #[structopt(flatten)]
+        reader: ReaderArgs,
+        #[structopt(flatten)]
+        parquet: ParquetWriterArgs,
+        #[structopt(flatten)]
+        avro: AvroWriterArgs,
+        #[structopt(flatten)]
+        csv: CsvWriterArgs,
+        #[structopt(flatten)]
+        json: JsonWriterArgs,
+    },
+    /// Merge files of any supported format by column name into one output file
+    Merge {
+        /// Input files followed by the output file
+        #[structopt(parse(from_os_str), required = true, min_values = 3)]
+        files: Vec<PathBuf>,
+        /// Output in a single file
+        #[structopt(short, long, conflicts_with_all = &["max-rows-per-file", "target-file-size"])]
         single_file: bool,
-        /// Enable Zstd compression
+        /// Remove duplicate rows
+        #[structopt(long)]
+        distinct: bool,
+        /// Sort the output by a comma-separated list of columns, each followed by an optional
+        /// asc|desc and nulls first|last, such as "region, date desc"
+        #[structopt(
+            long,
+            parse(try_from_str = parse_sort_column),
+            use_delimiter = true,
+            number_of_values = 1
+        )]
+        sort_by: Vec<SortColumn>,
+        /// Memory available to sorts and aggregations before they spill to temporary files,
+        /// such as 512MB [default: 4GB]
+        #[structopt(long, parse(try_from_str = parse_size))]
+        memory_limit: Option<usize>,
+        /// Overwrite the output if it already exists
+        #[structopt(long)]
+        force: bool,
+        /// Do not show progress or print a summary when finished
         #[structopt(short, long)]
-        zstd: bool,
-        #[structopt(parse(from_os_str))]
-        input: PathBuf,
-        #[structopt(parse(from_os_str))]
-        output: PathBuf,
+        quiet: bool,
+        #[structopt(flatten)]
+        split: FileSplitArgs,
+        #[structopt(flatten)]
+        reader: ReaderArgs,
+        #[structopt(flatten)]
+        parquet: ParquetWriterArgs,
+        #[structopt(flatten)]
+        avro: AvroWriterArgs,
+        #[structopt(flatten)]
+        csv: CsvWriterArgs,
+        #[structopt(flatten)]
+        json: JsonWriterArgs,
+    },
+    /// Rewrite Parquet files with new writer settings, such as compacting many small files into
+    /// a few large ones
+    Rewrite {
+        /// Parquet files or directories of Parquet files, followed by the output file or
+        /// directory
+        #[structopt(parse(from_os_str), required = true, min_values = 2)]
+        files: Vec<PathBuf>,
+        /// Number of output files to write, with the rows split evenly between them
+        #[structopt(long, conflicts_with_all = &["max-rows-per-file", "target-file-size"])]
+        num_files: Option<usize>,
+        /// Overwrite the output if it already exists
+        #[structopt(long)]
+        force: bool,
+        /// Do not show progress or print a summary when finished
+        #[structopt(short, long)]
+        quiet: bool,
+        #[structopt(flatten)]
+        split: FileSplitArgs,
+        #[structopt(flatten)]
+        parquet: ParquetWriterArgs,
     },
     /// Show the row count of the file
     Count {
         #[structopt(parse(from_os_str), long)]
         table: PathBuf,
+        #[structopt(flatten)]
+        reader: ReaderArgs,
     },
     /// Run a SQL query against one or more files
     Query {
@@ -75,14 +270,70 @@ enum Command {
         /// will be written to stdout
         #[structopt(parse(from_os_str), long)]
         output: Option<PathBuf>,
+        /// Output in a single file
+        #[structopt(short, long, conflicts_with_all = &["max-rows-per-file", "target-file-size"])]
+        single_file: bool,
         /// Enable verbose logging
         #[structopt(short, long)]
         verbose: bool,
+        /// Memory available to sorts and aggregations before they spill to temporary files,
+        /// such as 512MB [default: 4GB]
+        #[structopt(long, parse(try_from_str = parse_size))]
+        memory_limit: Option<usize>,
+        /// Overwrite the output if it already exists
+        #[structopt(long)]
+        force: bool,
+        /// Do not show progress or print a summary when finished
+        #[structopt(short, long)]
+        quiet: bool,
+        #[structopt(flatten)]
+        split: FileSplitArgs,
+        #[structopt(flatten)]
+        reader: ReaderArgs,
+        #[structopt(flatten)]
+        avro: AvroWriterArgs,
+        #[structopt(flatten)]
+        csv: CsvWriterArgs,
+        #[structopt(flatten)]
+        json: JsonWriterArgs,
     },
     /// View Parquet metadata
     ViewParquetMeta {
         #[structopt(parse(from_os_str))]
         input: PathBuf,
+        /// Output format (table, json, or csv)
+        #[structopt(long, default_value = "table", parse(try_from_str = parse_report_format))]
+        format: ReportFormat,
+    },
+    /// View the pages of a column in a Parquet file
+    ViewParquetPages {
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+        /// Column to show the pages of, such as address.city for a nested column
+        #[structopt(long)]
+        column: String,
+        /// Only show the pages in this row group
+        #[structopt(long)]
+        row_group: Option<usize>,
+    },
+    /// Check which row groups of a Parquet file might contain a value using bloom filters
+    ParquetBloom {
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+        /// Column to check
+        #[structopt(long)]
+        column: String,
+        /// Value to look for
+        #[structopt(long)]
+        value: String,
+    },
+    /// View Avro container metadata
+    ViewAvroMeta {
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+        /// Avro schema file to check for compatibility with the writer schema
+        #[structopt(parse(from_os_str), long)]
+        reader_schema: Option<PathBuf>,
     },
     /// Compare the contents of two files
     Compare {
@@ -95,25 +346,346 @@ enum Command {
         /// Assume there is a header row by default (only applies to CSV)
         #[structopt(short, long)]
         no_header_row: bool,
+        /// Do not show progress or print a summary when finished
+        #[structopt(short, long)]
+        quiet: bool,
+        #[structopt(flatten)]
+        reader: ReaderArgs,
+    },
+    /// Detect the dialect of a CSV file
+    Sniff {
+        #[structopt(parse(from_os_str))]
+        filename: PathBuf,
     },
 }
 
-#[tokio::main]
-async fn main() {
+impl Command {
+    fn memory_limit(&self) -> Option<usize> {
+        match self {
+            Command::Convert { memory_limit, .. }
+            | Command::Merge { memory_limit, .. }
+            | Command::Query { memory_limit, .. } => *memory_limit,
+            _ => None,
+        }
+    }
+}
+
+// Options for reading input files, shared by all commands that read files
+#[derive(Debug, StructOpt)]
+struct ReaderArgs {
+    /// Detect the CSV delimiter, quote character and header row by sampling the file
+    #[structopt(long)]
+    auto_dialect: bool,
+    /// Dot-separated path to the records within each JSON document, such as `data.items`
+    #[structopt(long)]
+    json_path: Option<String>,
+    /// Explicit schema for CSV and JSON files, either a path to a JSON schema file or a list of
+    /// column definitions such as "id INT, name TEXT"
+    #[structopt(long)]
+    schema: Option<String>,
+    /// Fail if the columns in the file do not match the columns in the schema
+    #[structopt(long, requires = "schema")]
+    strict_schema: bool,
+}
+
+// Options for writing Parquet files
+#[derive(Debug, StructOpt)]
+struct ParquetWriterArgs {
+    /// Enable Zstd compression (level 8)
+    #[structopt(short, long)]
+    zstd: bool,
+    /// Parquet compression codec with optional level (none, snappy, gzip, brotli, lz4, lz4_raw,
+    /// zstd), such as zstd:8
+    #[structopt(long, parse(try_from_str = parse_compression), conflicts_with = "zstd")]
+    compression: Option<Compression>,
+    /// Disable dictionary encoding
+    #[structopt(long)]
+    no_dictionary: bool,
+    /// Encoding for a column, such as id=delta or name=delta_byte_array. Can be repeated
+    #[structopt(long, parse(try_from_str = parse_column_encoding), number_of_values = 1)]
+    column_encoding: Vec<(String, Encoding)>,
+    /// Maximum number of rows in each row group
+    #[structopt(long)]
+    max_row_group_size: Option<usize>,
+    /// Maximum size of each data page in bytes
+    #[structopt(long)]
+    data_page_size: Option<usize>,
+    /// Parquet writer version (1 or 2)
+    #[structopt(long, parse(try_from_str = parse_writer_version))]
+    writer_version: Option<WriterVersion>,
+    /// Statistics level (none, chunk, or page)
+    #[structopt(long, parse(try_from_str = parse_statistics))]
+    statistics: Option<EnabledStatistics>,
+    /// Maximum size of min/max statistics in bytes
+    #[structopt(long)]
+    max_statistics_size: Option<usize>,
+    /// Write a bloom filter for a column. Can be repeated
+    #[structopt(long, number_of_values = 1)]
+    bloom_filter: Vec<String>,
+    /// False positive probability for bloom filters
+    #[structopt(long, requires = "bloom-filter")]
+    bloom_filter_fpp: Option<f64>,
+    /// Expected number of distinct values per row group for sizing bloom filters
+    #[structopt(long, requires = "bloom-filter")]
+    bloom_filter_ndv: Option<u64>,
+    /// Write column indexes and offset indexes (enables page statistics)
+    #[structopt(long, conflicts_with = "statistics")]
+    page_index: bool,
+    /// Key-value metadata to write to the file footer, such as schema_version=3. Can be repeated
+    #[structopt(long, parse(try_from_str = parse_key_value), number_of_values = 1)]
+    metadata: Vec<(String, String)>,
+    /// JSON file containing an object of key-value metadata to write to the file footer
+    #[structopt(parse(from_os_str), long)]
+    metadata_file: Option<PathBuf>,
+    /// Copy the key-value metadata of Parquet input files to the output
+    #[structopt(long)]
+    keep_metadata: bool,
+}
+
+impl ParquetWriterArgs {
+    fn options(&self) -> Result<ParquetWriterOptions, Error> {
+        let compression = if self.zstd {
+            Some(Compression::ZSTD(ZstdLevel::try_new(8)?))
+        } else {
+            self.compression
+        };
+        let mut key_value_metadata = match &self.metadata_file {
+            Some(path) => load_metadata_file(path)?,
+            None => vec![],
+        };
+        for (key, value) in &self.metadata {
+            key_value_metadata.retain(|(k, _)| k != key);
+            key_value_metadata.push((key.clone(), value.clone()));
+        }
+        Ok(ParquetWriterOptions {
+            compression,
+            disable_dictionary: self.no_dictionary,
+            column_encodings: self.column_encoding.clone(),
+            max_row_group_size: self.max_row_group_size,
+            data_page_size: self.data_page_size,
+            writer_version: self.writer_version,
+            statistics: self.statistics,
+            max_statistics_size: self.max_statistics_size,
+            bloom_filter_columns: self.bloom_filter.clone(),
+            bloom_filter_fpp: self.bloom_filter_fpp,
+            bloom_filter_ndv: self.bloom_filter_ndv,
+            page_index: self.page_index,
+            sorting_columns: vec![],
+            key_value_metadata,
+            keep_input_metadata: self.keep_metadata,
+        })
+    }
+}
+
+// Options for splitting output into multiple files
+#[derive(Debug, StructOpt)]
+struct FileSplitArgs {
+    /// Start a new output file after this many rows
+    #[structopt(long)]
+    max_rows_per_file: Option<usize>,
+    /// Start a new output file once the current file reaches this size, such as 128MB
+    #[structopt(long, parse(try_from_str = parse_size))]
+    target_file_size: Option<usize>,
+}
+
+impl FileSplitArgs {
+    fn options(&self) -> FileSplitOptions {
+        FileSplitOptions {
+            max_rows_per_file: self.max_rows_per_file,
+            target_file_size: self.target_file_size,
+        }
+    }
+}
+
+// Options for writing Avro files
+#[derive(Debug, StructOpt)]
+struct AvroWriterArgs {
+    /// Avro compression codec (null, deflate, snappy, or zstd)
+    #[structopt(long, default_value = "null", parse(try_from_str = parse_avro_codec))]
+    avro_codec: apache_avro::Codec,
+    /// Avro schema file to use when writing Avro files
+    #[structopt(parse(from_os_str), long)]
+    avro_schema: Option<PathBuf>,
+}
+
+impl AvroWriterArgs {
+    fn options(&self) -> AvroWriterOptions {
+        AvroWriterOptions {
+            codec: self.avro_codec,
+            schema: self.avro_schema.clone(),
+        }
+    }
+}
+
+// Options for writing CSV files
+#[derive(Debug, StructOpt)]
+struct CsvWriterArgs {
+    /// Field delimiter for CSV output, such as ';' or 'tab'
+    #[structopt(long, default_value = ",", parse(try_from_str = parse_delimiter))]
+    csv_delimiter: u8,
+    /// When to quote fields in CSV output (always, necessary, or never)
+    #[structopt(long, default_value = "necessary", parse(try_from_str = parse_quote_style))]
+    csv_quote_style: QuoteStyle,
+    /// Do not write a header row to CSV output
+    #[structopt(long)]
+    csv_no_header: bool,
+    /// Text to write for null values in CSV output
+    #[structopt(long, default_value = "")]
+    csv_null: String,
+    /// Line ending for CSV output (lf or crlf)
+    #[structopt(long, default_value = "lf", parse(try_from_str = parse_line_ending))]
+    csv_line_ending: LineTerminator,
+    /// Format for dates in CSV output, using strftime syntax such as '%d/%m/%Y'
+    #[structopt(long)]
+    csv_date_format: Option<String>,
+    /// Format for times in CSV output, using strftime syntax such as '%H:%M'
+    #[structopt(long)]
+    csv_time_format: Option<String>,
+    /// Format for timestamps in CSV output, using strftime syntax such as '%Y-%m-%d %H:%M:%S'
+    #[structopt(long)]
+    csv_timestamp_format: Option<String>,
+    /// Start CSV output with a UTF-8 byte order mark
+    #[structopt(long)]
+    csv_bom: bool,
+}
+
+impl CsvWriterArgs {
+    fn options(&self) -> CsvWriterOptions {
+        CsvWriterOptions {
+            delimiter: self.csv_delimiter,
+            quote_style: self.csv_quote_style,
+            header: !self.csv_no_header,
+            null: self.csv_null.clone(),
+            line_terminator: self.csv_line_ending,
+            date_format: self.csv_date_format.clone(),
+            time_format: self.csv_time_format.clone(),
+            timestamp_format: self.csv_timestamp_format.clone(),
+            bom: self.csv_bom,
+        }
+    }
+}
+
+// Options for writing JSON files
+#[derive(Debug, StructOpt)]
+struct JsonWriterArgs {
+    /// Shape of JSON output: ndjson for one object per line, or array for a single JSON array
+    #[structopt(long, default_value = "ndjson", parse(try_from_str = parse_json_format))]
+    json_format: JsonFormat,
+    /// Indent JSON output over multiple lines
+    #[structopt(long)]
+    json_pretty: bool,
+    /// Write keys with null values in JSON output instead of omitting them
+    #[structopt(long)]
+    json_explicit_nulls: bool,
+    /// How to write decimals in JSON output (number or string)
+    #[structopt(long, default_value = "number", parse(try_from_str = parse_decimal_format))]
+    json_decimal: DecimalFormat,
+    /// How to write binary values in JSON output (base64 or hex)
+    #[structopt(long, default_value = "base64", parse(try_from_str = parse_binary_format))]
+    json_binary: BinaryFormat,
+    /// How to write timestamps in JSON output (iso8601, or seconds, millis, micros, or nanos
+    /// since the Unix epoch)
+    #[structopt(long, default_value = "iso8601", parse(try_from_str = parse_timestamp_format))]
+    json_timestamp: TimestampFormat,
+}
+
+impl JsonWriterArgs {
+    fn options(&self) -> JsonWriterOptions {
+        JsonWriterOptions {
+            format: self.json_format,
+            pretty: self.json_pretty,
+            explicit_nulls: self.json_explicit_nulls,
+            decimal: self.json_decimal,
+            binary: self.json_binary,
+            timestamp: self.json_timestamp.clone(),
+        }
+    }
+}
+
+impl ReaderArgs {
+    fn options(&self) -> ReaderOptions {
+        ReaderOptions {
+            auto_dialect: self.auto_dialect,
+            json_path: self.json_path.clone(),
+            schema: self.schema.clone(),
+            strict_schema: self.strict_schema,
+            ..Default::default()
+        }
+    }
+}
+
+fn main() {
     let cmd = Command::from_args();
-    if let Err(e) = execute_command(cmd).await {
-        println!("{:?}", e);
-        std::process::exit(-1);
+    let runtime = tokio::runtime::Runtime::new().expect("failed to start the async runtime");
+    let interrupted = Box::pin(interrupted());
+    // the command runs on its own task, so that an interrupt is noticed while it is busy
+    let command = runtime.spawn(execute_command(cmd));
+    let result = runtime.block_on(async move {
+        match futures::future::select(command, interrupted).await {
+            Either::Left((result, _)) => Some(result),
+            Either::Right(_) => None,
+        }
+    });
+    match result {
+        Some(Ok(Ok(()))) => {}
+        Some(Ok(Err(e))) => {
+            println!("{:?}", e);
+            std::process::exit(-1);
+        }
+        Some(Err(e)) => std::panic::resume_unwind(e.into_panic()),
+        None => {
+            // stop the tasks that are still writing before removing their temporary output,
+            // so that no writer recreates it afterwards
+            runtime.shutdown_timeout(Duration::from_secs(10));
+            remove_pending_outputs();
+            std::process::exit(130);
+        }
+    }
+}
+
+/// Returns a future that completes when the process is interrupted with Ctrl-C or terminated,
+/// so that partially written output can be removed instead of being left next to the output
+#[cfg(unix)]
+fn interrupted() -> impl std::future::Future<Output = ()> {
+    use signal_hook::consts::{SIGINT, SIGTERM};
+    use signal_hook::iterator::Signals;
+    let (tx, rx) = futures::channel::oneshot::channel();
+    if let Ok(mut signals) = Signals::new([SIGINT, SIGTERM]) {
+        std::thread::spawn(move || {
+            if signals.forever().next().is_some() {
+                let _ = tx.send(());
+            }
+        });
+    }
+    async move {
+        // the sender is dropped without sending when the signals could not be registered
+        if rx.await.is_err() {
+            futures::future::pending::<()>().await;
+        }
     }
 }
 
+#[cfg(not(unix))]
+fn interrupted() -> impl std::future::Future<Output = ()> {
+    futures::future::pending()
+}
+
 async fn execute_command(cmd: Command) -> Result<(), Error> {
     let config = SessionConfig::new().with_information_schema(true);
-    let ctx = SessionContext::new_with_config(config);
+    // operators such as sorts spill to temporary files once the memory limit is reached
+    let memory_limit = cmd.memory_limit().unwrap_or(DEFAULT_MEMORY_LIMIT);
+    let runtime = RuntimeConfig::new()
+        .with_memory_pool(Arc::new(FairSpillPool::new(memory_limit)))
+        .with_disk_manager(DiskManagerConfig::NewOs);
+    let ctx = SessionContext::new_with_config_rt(config, Arc::new(RuntimeEnv::new(runtime)?));
     match cmd {
-        Command::View { filename, limit } => {
+        Command::View {
+            filename,
+            limit,
+            reader,
+        } => {
             let filename = parse_filename(&filename)?;
-            let df = register_table(&ctx, "t", filename).await?;
+            let df = register_table(&ctx, "t", filename, &reader.options()).await?;
             let limit = limit.unwrap_or(10);
             if limit > 0 {
                 df.show_limit(limit).await?;
@@ -125,9 +697,9 @@ async fn execute_command(cmd: Command) -> Result<(), Error> {
                 df.show().await?;
             }
         }
-        Command::Schema { filename } => {
+        Command::Schema { filename, reader } => {
             let filename = parse_filename(&filename)?;
-            let _ = register_table(&ctx, "t", filename).await?;
+            let _ = register_table(&ctx, "t", filename, &reader.options()).await?;
             let sql = "SELECT column_name, data_type, is_nullable \
                                 FROM information_schema.columns WHERE table_name = 't'";
             let df = ctx.sql(sql).await?;
@@ -137,11 +709,82 @@ async fn execute_command(cmd: Command) -> Result<(), Error> {
             single_file,
             input,
             output,
-            zstd,
+            input_dir,
+            output_dir,
+            to,
+            include,
+            jobs,
+            select,
+            filter,
+            rename,
+            sql,
+            partition_by,
+            exclude_partition_columns,
+            max_open_files,
+            sort_by,
+            cast,
+            target_schema,
+            null_on_cast_error,
+            verify,
+            strict,
+            force,
+            quiet,
+            split,
+            reader,
+            parquet,
+            avro,
+            csv,
+            json,
+            ..
         } => {
-            let input_filename = parse_filename(&input)?;
-            let output_filename = parse_filename(&output)?;
-            convert_files(&ctx, input_filename, output_filename, single_file, zstd).await?;
+            let options = ConvertOptions {
+                single_file,
+                reader: reader.options(),
+                parquet: parquet.options()?,
+                avro: avro.options(),
+                csv: csv.options(),
+                json: json.options(),
+                select,
+                filter,
+                renames: rename,
+                sql,
+                partition: PartitionOptions {
+                    columns: partition_by,
+                    exclude_columns: exclude_partition_columns,
+                    max_open_files,
+                },
+                sort_by,
+                split: split.options(),
+                cast: CastOptions {
+                    casts: cast,
+                    target_schema,
+                    null_on_error: null_on_cast_error,
+                },
+                verify,
+                strict,
+                force,
+                quiet,
+            };
+            match (input_dir, output_dir, to, input, output) {
+                (Some(input_dir), Some(output_dir), Some(to), _, _) => {
+                    let jobs = jobs.unwrap_or_else(|| {
+                        std::thread::available_parallelism().map_or(1, |n| n.get())
+                    });
+                    let batch = BatchConvertOptions { include, to, jobs };
+                    convert_dir(&ctx, &input_dir, &output_dir, &batch, &options).await?;
+                }
+                (_, _, _, Some(input), Some(output)) => {
+                    let input_filename = parse_filename(&input)?;
+                    let output_filename = parse_filename(&output)?;
+                    convert_files(&ctx, input_filename, output_filename, &options).await?;
+                }
+                _ => {
+                    return Err(Error::General(
+                        "Specify an input and output file, or --input-dir, --output-dir and --to"
+                            .to_string(),
+                    ))
+                }
+            }
         }
         Command::Query {
             table,
@@ -149,19 +792,32 @@ async fn execute_command(cmd: Command) -> Result<(), Error> {
             sql,
             sql_file,
             output,
+            single_file,
             verbose,
+            force,
+            quiet,
+            split,
+            reader,
+            avro,
+            csv,
+            json,
+            ..
         } => {
+            let reader_options = reader.options();
+            let mut inputs = table.clone();
             if let Some(dir) = tables {
                 let paths = fs::read_dir(&dir)?;
                 for path in paths {
                     let path = path?.path();
+                    inputs.push(path.clone());
                     let file_name =
                         path.file_stem().unwrap().to_str().ok_or_else(|| {
                             DataFusionError::Internal("Invalid filename".to_string())
                         })?;
                     let table_name = sanitize_table_name(file_name);
                     println!("Registering table '{}' for {}", table_name, path.display());
-                    register_table(&ctx, &table_name, parse_filename(&path)?).await?;
+                    register_table(&ctx, &table_name, parse_filename(&path)?, &reader_options)
+                        .await?;
                 }
             }
             for table in &table {
@@ -172,7 +828,7 @@ async fn execute_command(cmd: Command) -> Result<(), Error> {
                     .ok_or_else(|| DataFusionError::Internal("Invalid filename".to_string()))?;
                 let table_name = sanitize_table_name(file_name);
                 println!("Registering table '{}' for {}", table_name, table.display());
-                register_table(&ctx, &table_name, parse_filename(table)?).await?;
+                register_table(&ctx, &table_name, parse_filename(table)?, &reader_options).await?;
             }
             let sql = match (sql, sql_file) {
                 (Some(text), None) => text,
@@ -184,66 +840,139 @@ async fn execute_command(cmd: Command) -> Result<(), Error> {
                 let explain = df.clone().explain(false, false)?;
                 explain.show().await?;
             }
+            let options = ConvertOptions {
+                single_file,
+                avro: avro.options(),
+                csv: csv.options(),
+                json: json.options(),
+                split: split.options(),
+                force,
+                quiet,
+                ..Default::default()
+            };
             if let Some(path) = output {
-                match path.extension() {
-                    Some(x) => match x.to_str().unwrap() {
-                        "csv" => {
-                            println!("Writing results in CSV format to {}", path.display());
-                            let _ = df
-                                .write_csv(
-                                    path.to_str().unwrap(),
-                                    DataFrameWriteOptions::default(),
-                                    None,
-                                )
-                                .await?;
-                        }
-                        "parquet" => {
-                            println!("Writing results in Parquet format to {}", path.display());
-                            let _ = df
-                                .write_parquet(
-                                    path.to_str().unwrap(),
-                                    DataFrameWriteOptions::default(),
-                                    None,
-                                )
-                                .await?;
-                        }
-                        _ => {
-                            return Err(Error::General(
-                                "Unsupported file format for saving query results".to_string(),
-                            ))
-                        }
-                    },
-                    _ => {
-                        return Err(Error::General(
-                            "Unsupported file format for saving query results".to_string(),
-                        ))
-                    }
-                }
+                let inputs = inputs
+                    .iter()
+                    .map(|path| parse_filename(path))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let progress = Progress::start(&inputs, false, quiet);
+                write_output(df, parse_filename(&path)?, &progress, &options).await?;
             } else {
                 df.show().await?;
             }
         }
-        Command::Count { table } => {
+        Command::Merge {
+            files,
+            single_file,
+            distinct,
+            sort_by,
+            force,
+            quiet,
+            split,
+            reader,
+            parquet,
+            avro,
+            csv,
+            json,
+            ..
+        } => {
+            let (output, inputs) = files.split_last().unwrap();
+            let input_filenames = inputs
+                .iter()
+                .map(|path| parse_filename(path))
+                .collect::<Result<Vec<_>, _>>()?;
+            let options = ConvertOptions {
+                single_file,
+                reader: reader.options(),
+                parquet: parquet.options()?,
+                avro: avro.options(),
+                csv: csv.options(),
+                json: json.options(),
+                sort_by,
+                split: split.options(),
+                force,
+                quiet,
+                ..Default::default()
+            };
+            merge_files(
+                &ctx,
+                &input_filenames,
+                parse_filename(output)?,
+                distinct,
+                &options,
+            )
+            .await?;
+        }
+        Command::Rewrite {
+            files,
+            num_files,
+            force,
+            quiet,
+            split,
+            parquet,
+        } => {
+            let (output, inputs) = files.split_last().unwrap();
+            let options = ConvertOptions {
+                parquet: parquet.options()?,
+                split: split.options(),
+                force,
+                quiet,
+                ..Default::default()
+            };
+            rewrite_files(&ctx, inputs, parse_filename(output)?, num_files, &options).await?;
+        }
+        Command::Count { table, reader } => {
             let table_name = "__t1__";
-            register_table(&ctx, table_name, parse_filename(&table)?).await?;
+            register_table(&ctx, table_name, parse_filename(&table)?, &reader.options()).await?;
             let sql = format!("SELECT COUNT(*) FROM {}", table_name);
             let df = ctx.sql(&sql).await?;
             df.show().await?;
         }
-        Command::ViewParquetMeta { input } => {
-            view_parquet_meta(input)?;
+        Command::ViewParquetMeta { input, format } => {
+            view_parquet_meta(input, format)?;
+        }
+        Command::ViewParquetPages {
+            input,
+            column,
+            row_group,
+        } => {
+            view_parquet_pages(input, &column, row_group)?;
+        }
+        Command::ParquetBloom {
+            input,
+            column,
+            value,
+        } => {
+            probe_bloom_filter(input, &column, &value)?;
+        }
+        Command::ViewAvroMeta {
+            input,
+            reader_schema,
+        } => {
+            view_avro_meta(input, reader_schema)?;
         }
         Command::Compare {
             input1,
             input2,
             epsilon,
             no_header_row,
-        } => match compare::compare_files(input1, input2, !no_header_row, epsilon).await? {
-            ComparisonResult::Ok => {
-                println!("Files match");
+            quiet,
+            reader,
+        } => {
+            let reader_options = ReaderOptions {
+                has_header: !no_header_row,
+                ..reader.options()
+            };
+            match compare::compare_files(input1, input2, epsilon, &reader_options, quiet).await? {
+                ComparisonResult::Ok => {
+                    println!("Files match");
+                }
+                diff => return Err(Error::General(format!("{}", diff))),
             }
-            diff => return Err(Error::General(format!("{}", diff))),
-        },
+        }
+        Command::Sniff { filename } => {
+            view_csv_dialect(&filename)?;
+        }
     }
     Ok(())
 }
@@ -0,0 +1,222 @@
+use crate::schema::{load_schema, parse_data_type};
+use crate::Error;
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use datafusion::arrow::array::{ArrayRef, Int32Array, Int64Array};
+use datafusion::arrow::compute;
+use datafusion::arrow::datatypes::{DataType, TimeUnit};
+use datafusion::common::cast::as_string_array;
+use datafusion::error::DataFusionError;
+use datafusion::logical_expr::{create_udf, ColumnarValue, Expr, ScalarUDF, Volatility};
+use datafusion::prelude::{cast, ident, try_cast, DataFrame, SessionContext};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// A cast of a column to a SQL type, with an optional format for parsing dates and timestamps
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnCast {
+    pub column: String,
+    pub data_type: String,
+    pub format: Option<String>,
+}
+
+/// Parse a column cast in the form `column=TYPE` or `column=TYPE:format`, such as
+/// `price=DECIMAL(18,4)` or `ts=TIMESTAMP:%d/%m/%Y %H:%M`. Formats use the `strftime` syntax.
+pub fn parse_column_cast(value: &str) -> Result<ColumnCast, Error> {
+    let (column, data_type) = value
+        .split_once('=')
+        .ok_or_else(|| Error::General(format!("Invalid cast '{}', expected column=TYPE", value)))?;
+    let (data_type, format) = match data_type.split_once(':') {
+        Some((data_type, format)) => (data_type, Some(format.to_string())),
+        None => (data_type, None),
+    };
+    Ok(ColumnCast {
+        column: column.to_string(),
+        data_type: data_type.trim().to_string(),
+        format,
+    })
+}
+
+/// Options for casting columns before writing
+#[derive(Debug, Clone, Default)]
+pub struct CastOptions {
+    /// Casts for individual columns
+    pub casts: Vec<ColumnCast>,
+    /// Schema file or column definitions that the output must match. Columns are written in the
+    /// order of the schema and other input columns are dropped.
+    pub target_schema: Option<String>,
+    /// Write null for values that cannot be cast instead of failing
+    pub null_on_error: bool,
+}
+
+impl CastOptions {
+    pub fn is_empty(&self) -> bool {
+        self.casts.is_empty() && self.target_schema.is_none()
+    }
+}
+
+/// Cast the columns of a DataFrame to the requested types
+pub async fn apply_casts(
+    ctx: &SessionContext,
+    df: DataFrame,
+    options: &CastOptions,
+) -> Result<DataFrame, Error> {
+    if options.is_empty() {
+        return Ok(df);
+    }
+    let mut casts = HashMap::new();
+    for column_cast in &options.casts {
+        if df
+            .schema()
+            .field_with_unqualified_name(&column_cast.column)
+            .is_err()
+        {
+            return Err(Error::General(format!(
+                "Cannot cast column '{}' because it does not exist",
+                column_cast.column
+            )));
+        }
+        let data_type = parse_data_type(ctx, &column_cast.data_type).await?;
+        casts.insert(
+            column_cast.column.as_str(),
+            (data_type, &column_cast.format),
+        );
+    }
+
+    let columns: Vec<(String, Option<DataType>)> = match &options.target_schema {
+        Some(target_schema) => {
+            let schema = load_schema(ctx, target_schema).await?;
+            let missing: Vec<&str> = schema
+                .fields()
+                .iter()
+                .map(|f| f.name().as_str())
+                .filter(|name| df.schema().field_with_unqualified_name(name).is_err())
+                .collect();
+            if !missing.is_empty() {
+                return Err(Error::General(format!(
+                    "Input is missing columns from the target schema: {}",
+                    missing.join(", ")
+                )));
+            }
+            schema
+                .fields()
+                .iter()
+                .map(|f| (f.name().to_string(), Some(f.data_type().clone())))
+                .collect()
+        }
+        None => df
+            .schema()
+            .fields()
+            .iter()
+            .map(|f| (f.name().to_string(), None))
+            .collect(),
+    };
+
+    let exprs = columns
+        .into_iter()
+        .map(|(name, target_type)| {
+            let mut expr = ident(&name);
+            if let Some((data_type, format)) = casts.get(name.as_str()) {
+                expr = cast_expr(expr, data_type, format.as_deref(), options.null_on_error)?;
+            }
+            if let Some(data_type) = target_type {
+                expr = cast_expr(expr, &data_type, None, options.null_on_error)?;
+            }
+            Ok(expr.alias(name))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    Ok(df.select(exprs)?)
+}
+
+fn cast_expr(
+    expr: Expr,
+    data_type: &DataType,
+    format: Option<&str>,
+    null_on_error: bool,
+) -> Result<Expr, Error> {
+    Ok(match format {
+        Some(format) => {
+            let parse = parse_datetime_udf(data_type, format, null_on_error)?;
+            parse.call(vec![cast(expr, DataType::Utf8)])
+        }
+        None if null_on_error => try_cast(expr, data_type.clone()),
+        None => cast(expr, data_type.clone()),
+    })
+}
+
+/// Create a function that parses strings to dates or timestamps using a `strftime` format
+fn parse_datetime_udf(
+    data_type: &DataType,
+    format: &str,
+    null_on_error: bool,
+) -> Result<ScalarUDF, Error> {
+    if !matches!(
+        data_type,
+        DataType::Date32 | DataType::Date64 | DataType::Timestamp(_, _)
+    ) {
+        return Err(Error::General(format!(
+            "A format can only be used when casting to a date or timestamp, not {}",
+            data_type
+        )));
+    }
+    let return_type = data_type.clone();
+    let format = format.to_string();
+    let fun = Arc::new(move |args: &[ColumnarValue]| {
+        let array = match &args[0] {
+            ColumnarValue::Array(array) => array.clone(),
+            ColumnarValue::Scalar(scalar) => scalar.to_array()?,
+        };
+        let values = as_string_array(&array)?
+            .iter()
+            .map(|value| match value {
+                Some(value) => match parse_datetime(value, &format, &return_type) {
+                    Some(parsed) => Ok(Some(parsed)),
+                    None if null_on_error => Ok(None),
+                    None => Err(DataFusionError::Execution(format!(
+                        "Cannot parse '{}' as {} with format '{}'",
+                        value, return_type, format
+                    ))),
+                },
+                None => Ok(None),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let array: ArrayRef = match return_type {
+            DataType::Date32 => Arc::new(
+                values
+                    .into_iter()
+                    .map(|v| v.map(|days| days as i32))
+                    .collect::<Int32Array>(),
+            ),
+            _ => Arc::new(Int64Array::from(values)),
+        };
+        Ok(ColumnarValue::Array(compute::cast(&array, &return_type)?))
+    });
+    Ok(create_udf(
+        "parse_datetime",
+        vec![DataType::Utf8],
+        Arc::new(data_type.clone()),
+        Volatility::Immutable,
+        fun,
+    ))
+}
+
+/// Parse a date or timestamp and return days since the epoch for `Date32`, milliseconds for
+/// `Date64`, or the timestamp in its time unit. Values without a time zone are treated as UTC.
+fn parse_datetime(value: &str, format: &str, data_type: &DataType) -> Option<i64> {
+    let datetime = DateTime::parse_from_str(value, format)
+        .map(|d| d.naive_utc())
+        .or_else(|_| NaiveDateTime::parse_from_str(value, format))
+        .or_else(|_| {
+            NaiveDate::parse_from_str(value, format).map(|d| d.and_hms_opt(0, 0, 0).unwrap())
+        })
+        .ok()?
+        .and_utc();
+    match data_type {
+        DataType::Date32 => Some(datetime.timestamp().div_euclid(86_400)),
+        DataType::Date64 => Some(datetime.timestamp_millis()),
+        DataType::Timestamp(TimeUnit::Second, _) => Some(datetime.timestamp()),
+        DataType::Timestamp(TimeUnit::Millisecond, _) => Some(datetime.timestamp_millis()),
+        DataType::Timestamp(TimeUnit::Microsecond, _) => Some(datetime.timestamp_micros()),
+        DataType::Timestamp(TimeUnit::Nanosecond, _) => datetime.timestamp_nanos_opt(),
+        _ => None,
+    }
+}
@@ -1,5 +1,7 @@
-use crate::utils::{file_format, RowIter};
+use crate::progress::Progress;
+use crate::utils::{file_format, register_table, ReaderOptions, RowIter};
 use crate::{Error, FileFormat};
+use datafusion::arrow::datatypes::DataType;
 use datafusion::arrow::record_batch::RecordBatch;
 use datafusion::common::ScalarValue;
 use datafusion::prelude::*;
@@ -10,21 +12,125 @@ use std::result::Result;
 pub async fn compare_files(
     path1: PathBuf,
     path2: PathBuf,
-    has_header: bool,
     epsilon: Option<f64>,
+    reader_options: &ReaderOptions,
+    quiet: bool,
 ) -> Result<ComparisonResult, Error> {
     let ctx = SessionContext::new();
     let filename1 = path1.to_str().unwrap();
     let filename2 = path2.to_str().unwrap();
     println!("Comparing {} with {}", filename1, filename2);
-    let batches1 = read_file(&ctx, filename1, has_header).await?;
-    let batches2 = read_file(&ctx, filename2, has_header).await?;
-    let count1: usize = batches1.iter().map(|b| b.num_rows()).sum();
-    let count2: usize = batches2.iter().map(|b| b.num_rows()).sum();
+    let progress = Progress::start(&[filename1, filename2], true, quiet);
+    let batches1 = read_file(&ctx, "t1", filename1, reader_options, &progress).await?;
+    let batches2 = read_file(&ctx, "t2", filename2, reader_options, &progress).await?;
+    let rows1 = RowIter::new(batches1).collect::<Result<Vec<_>, _>>()?;
+    let rows2 = RowIter::new(batches2).collect::<Result<Vec<_>, _>>()?;
+    let result = compare_rows(rows1, rows2, epsilon);
+    progress.finish(None);
+    Ok(result)
+}
+
+/// Compare the rows of two DataFrames regardless of their order, such as the data that was
+/// converted and the output file after reading it back.
+///
+/// Columns are matched by position. When a column has a different type in `right`, its values
+/// are compared after casting them to the type in `left` as long as both types are of the same
+/// kind, such as `Int32` and `Int64`. Other type changes, such as decimals that were read back
+/// as floats, are reported as differences.
+pub async fn compare_dataframes(
+    left: DataFrame,
+    right: DataFrame,
+    epsilon: Option<f64>,
+) -> Result<ComparisonResult, Error> {
+    let left_fields = left.schema().fields().clone();
+    let right_fields = right.schema().fields().clone();
+    if left_fields.len() != right_fields.len() {
+        return Ok(ComparisonResult::FileDiff(format!(
+            "column counts do not match: {} != {}",
+            left_fields.len(),
+            right_fields.len()
+        )));
+    }
+    let mut left_exprs = vec![];
+    let mut right_exprs = vec![];
+    let mut sort_exprs = vec![];
+    let mut orderable = vec![];
+    for (i, (l, r)) in left_fields.iter().zip(right_fields.iter()).enumerate() {
+        if l.name() != r.name() {
+            return Ok(ComparisonResult::FileDiff(format!(
+                "column names do not match at index {}: {} != {}",
+                i,
+                l.name(),
+                r.name()
+            )));
+        }
+        let (left_type, right_type) = (l.data_type(), r.data_type());
+        if left_type != right_type && !same_kind(left_type, right_type) {
+            return Ok(ComparisonResult::FileDiff(format!(
+                "column '{}' changed type from {} to {}",
+                l.name(),
+                left_type,
+                right_type
+            )));
+        }
+        let name = format!("c{}", i);
+        left_exprs
+            .push(cast(col(l.qualified_column()), value_type(left_type).clone()).alias(&name));
+        right_exprs
+            .push(cast(col(r.qualified_column()), value_type(left_type).clone()).alias(&name));
+        if is_orderable(value_type(left_type)) {
+            sort_exprs.push(ident(&name).sort(true, true));
+            orderable.push(i);
+        }
+    }
+    let left = left.select(left_exprs)?.sort(sort_exprs.clone())?;
+    let right = right.select(right_exprs)?.sort(sort_exprs)?;
+    Ok(compare_rows(
+        sorted_rows(left.collect().await?, &orderable)?,
+        sorted_rows(right.collect().await?, &orderable)?,
+        epsilon,
+    ))
+}
+
+/// Collect the rows of batches that are already sorted by the orderable columns, sorting each
+/// run of rows that tie on those columns by the text of the other columns, so that rows that
+/// only differ in nested values are in the same order on both sides
+fn sorted_rows(
+    batches: Vec<RecordBatch>,
+    orderable: &[usize],
+) -> Result<Vec<Vec<ScalarValue>>, Error> {
+    let mut rows = RowIter::new(batches).collect::<Result<Vec<_>, _>>()?;
+    if rows.first().is_none_or(|row| row.len() == orderable.len()) {
+        return Ok(rows);
+    }
+    let mut start = 0;
+    while start < rows.len() {
+        let first = &rows[start];
+        let run = rows[start..]
+            .iter()
+            .take_while(|row| orderable.iter().all(|&i| row[i] == first[i]))
+            .count();
+        rows[start..start + run].sort_by_cached_key(|row| {
+            row.iter()
+                .enumerate()
+                .filter(|(i, _)| !orderable.contains(i))
+                .map(|(_, value)| value.to_string())
+                .collect::<Vec<_>>()
+        });
+        start += run;
+    }
+    Ok(rows)
+}
+
+/// Compare rows in order, allowing floating point values to differ by up to `epsilon`
+fn compare_rows(
+    rows1: Vec<Vec<ScalarValue>>,
+    rows2: Vec<Vec<ScalarValue>>,
+    epsilon: Option<f64>,
+) -> ComparisonResult {
+    let (count1, count2) = (rows1.len(), rows2.len());
     if count1 == count2 {
-        let it1 = RowIter::new(batches1);
-        let it2 = RowIter::new(batches2);
-        for (i, (a, b)) in it1.zip(it2).enumerate() {
+        for (i, (a, b)) in rows1.into_iter().zip(rows2).enumerate() {
             if a.len() == b.len() {
                 for (j, (v1, v2)) in a.iter().zip(b.iter()).enumerate() {
                     if v1 != v2 {
@@ -48,7 +154,7 @@ pub async fn compare_files(
                                 "data does not match at row {} column {}: {:?} != {:?}",
                                 i, j, v1, v2
                             );
-                            return Ok(ComparisonResult::row_diff(a, b, message));
+                            return ComparisonResult::row_diff(a, b, message);
                         }
                     }
                 }
@@ -59,14 +165,58 @@ pub async fn compare_files(
                     a.len(),
                     b.len()
                 );
-                return Ok(ComparisonResult::row_diff(a, b, message));
+                return ComparisonResult::row_diff(a, b, message);
             }
         }
     } else {
         let message = format!("row counts do not match: {} != {}", count1, count2);
-        return Ok(ComparisonResult::FileDiff(message));
+        return ComparisonResult::FileDiff(message);
+    }
+    ComparisonResult::Ok
+}
+
+/// Whether values of two types can be compared after casting one to the other without the
+/// comparison hiding a change in meaning
+fn same_kind(a: &DataType, b: &DataType) -> bool {
+    let (a, b) = (value_type(a), value_type(b));
+    (a.is_integer() && b.is_integer())
+        || (a.is_floating() && b.is_floating())
+        || matches!(
+            (a, b),
+            (
+                DataType::Decimal128(_, _) | DataType::Decimal256(_, _),
+                DataType::Decimal128(_, _) | DataType::Decimal256(_, _)
+            ) | (
+                DataType::Utf8 | DataType::LargeUtf8,
+                DataType::Utf8 | DataType::LargeUtf8
+            ) | (
+                DataType::Binary | DataType::LargeBinary,
+                DataType::Binary | DataType::LargeBinary
+            ) | (
+                DataType::Date32 | DataType::Date64,
+                DataType::Date32 | DataType::Date64
+            ) | (DataType::Timestamp(_, _), DataType::Timestamp(_, _))
+        )
+}
+
+/// The type of the values of a dictionary, or the type itself for other types
+fn value_type(data_type: &DataType) -> &DataType {
+    match data_type {
+        DataType::Dictionary(_, value_type) => value_type,
+        other => other,
     }
-    Ok(ComparisonResult::Ok)
+}
+
+fn is_orderable(data_type: &DataType) -> bool {
+    data_type.is_primitive()
+        || matches!(
+            data_type,
+            DataType::Boolean
+                | DataType::Utf8
+                | DataType::LargeUtf8
+                | DataType::Binary
+                | DataType::LargeBinary
+        )
 }
 
 pub enum ComparisonResult {
@@ -115,29 +265,23 @@ impl Display for ComparisonResult {
 
 async fn read_file(
     ctx: &SessionContext,
+    table_name: &str,
     filename: &str,
-    has_header: bool,
+    reader_options: &ReaderOptions,
+    progress: &Progress,
 ) -> Result<Vec<RecordBatch>, Error> {
     match file_format(filename)? {
-        FileFormat::Csv => {
-            let read_options = CsvReadOptions::new().has_header(has_header);
-            ctx.read_csv(filename, read_options)
-                .await
-                .map_err(Error::from)?
+        FileFormat::Arrow => Err(Error::General(
+            "Unsupported file type for file comparison: Arrow".to_string(),
+        )),
+        _ => {
+            let df = register_table(ctx, table_name, filename, reader_options).await?;
+            progress
+                .track(df)
+                .await?
                 .collect()
                 .await
                 .map_err(Error::from)
         }
-        FileFormat::Parquet => ctx
-            .read_parquet(filename, ParquetReadOptions::default())
-            .await
-            .map_err(Error::from)?
-            .collect()
-            .await
-            .map_err(Error::from),
-        other => Err(Error::General(format!(
-            "Unsupported file type for file comparison: {:?}",
-            other
-        ))),
     }
 }
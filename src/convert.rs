@@ -1,54 +1,831 @@
-use crate::utils::{file_format, register_table};
+use crate::avro::{read_avro_batches, AvroWriterOptions};
+use crate::cast::{apply_casts, CastOptions};
+use crate::compare::{compare_dataframes, ComparisonResult};
+use crate::csv::CsvWriterOptions;
+use crate::json::{json_records_table, JsonWriterOptions, TimestampFormat};
+use crate::mapping::lossy_mappings;
+use crate::parquet::read_key_value_metadata;
+use crate::partition::PartitionOptions;
+use crate::progress::Progress;
+use crate::utils::{
+    file_ending, file_format, list_dir_files, register_table, strip_byte_order_mark, ReaderOptions,
+};
+use crate::writer::{write_empty_file, write_file, write_files, AtomicOutput, FileSplitOptions};
 use crate::{Error, FileFormat};
 
-use datafusion::prelude::SessionContext;
+use comfy_table::{Cell, Table};
+use datafusion::logical_expr::Expr;
+use datafusion::prelude::{
+    cast, ident, CsvReadOptions, DataFrame, ParquetReadOptions, SessionConfig, SessionContext,
+};
 use datafusion::{
+    arrow::datatypes::{DataType, Field, Schema, SchemaRef},
     arrow::record_batch::RecordBatch,
     dataframe::DataFrameWriteOptions,
+    datasource::MemTable,
+    execution::runtime_env::RuntimeEnv,
     parquet::{
-        basic::{Compression, Encoding, ZstdLevel},
-        file::properties::WriterProperties,
+        arrow::arrow_to_parquet_schema,
+        basic::{BrotliLevel, Compression, Encoding, GzipLevel, ZstdLevel},
+        file::properties::{EnabledStatistics, WriterProperties, WriterVersion},
+        format::{KeyValue, SortingColumn},
+        schema::types::ColumnPath,
     },
 };
+use futures::StreamExt;
+use glob::{MatchOptions, Pattern};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Options for writing Parquet files. Settings that are not specified use the defaults of the
+/// Parquet writer.
+#[derive(Debug, Clone, Default)]
+pub struct ParquetWriterOptions {
+    /// Compression codec and level
+    pub compression: Option<Compression>,
+    /// Disable dictionary encoding for all columns
+    pub disable_dictionary: bool,
+    /// Encodings for specific columns, which also disables dictionary encoding for those columns
+    pub column_encodings: Vec<(String, Encoding)>,
+    /// Maximum number of rows in each row group
+    pub max_row_group_size: Option<usize>,
+    /// Best effort maximum size of a data page in bytes
+    pub data_page_size: Option<usize>,
+    /// Parquet format version (1 or 2)
+    pub writer_version: Option<WriterVersion>,
+    /// Level of statistics to write (none, chunk, or page)
+    pub statistics: Option<EnabledStatistics>,
+    /// Maximum size of min/max statistics in bytes
+    pub max_statistics_size: Option<usize>,
+    /// Columns to write bloom filters for
+    pub bloom_filter_columns: Vec<String>,
+    /// False positive probability for bloom filters
+    pub bloom_filter_fpp: Option<f64>,
+    /// Expected number of distinct values for sizing bloom filters
+    pub bloom_filter_ndv: Option<u64>,
+    /// Write column indexes in addition to offset indexes, which requires page statistics
+    pub page_index: bool,
+    /// Sort order of the data, which is recorded in the row group metadata
+    pub sorting_columns: Vec<SortColumn>,
+    /// Key-value metadata to write to the file footer and the Arrow schema
+    pub key_value_metadata: Vec<(String, String)>,
+    /// Copy the key-value metadata of Parquet input files to the output
+    pub keep_input_metadata: bool,
+}
+
+impl ParquetWriterOptions {
+    /// Writer properties for files with the given schema, which is needed to resolve the sort
+    /// order to column indexes
+    pub fn writer_properties(&self, schema: &Schema) -> Result<WriterProperties, Error> {
+        let mut builder = WriterProperties::builder()
+            .set_created_by("bdt".to_string())
+            .set_dictionary_enabled(!self.disable_dictionary);
+        if let Some(compression) = self.compression {
+            builder = builder.set_compression(compression);
+        }
+        for (column, encoding) in &self.column_encodings {
+            let path = column_path(column);
+            builder = builder
+                .set_column_dictionary_enabled(path.clone(), false)
+                .set_column_encoding(path, *encoding);
+        }
+        if let Some(size) = self.max_row_group_size {
+            builder = builder.set_max_row_group_size(size);
+        }
+        if let Some(size) = self.data_page_size {
+            builder = builder.set_data_page_size_limit(size);
+        }
+        if let Some(version) = self.writer_version {
+            builder = builder.set_writer_version(version);
+        }
+        if let Some(statistics) = self.statistics {
+            builder = builder.set_statistics_enabled(statistics);
+        }
+        if let Some(size) = self.max_statistics_size {
+            builder = builder.set_max_statistics_size(size);
+        }
+        for column in &self.bloom_filter_columns {
+            let path = column_path(column);
+            builder = builder.set_column_bloom_filter_enabled(path.clone(), true);
+            if let Some(fpp) = self.bloom_filter_fpp {
+                builder = builder.set_column_bloom_filter_fpp(path.clone(), fpp);
+            }
+            if let Some(ndv) = self.bloom_filter_ndv {
+                builder = builder.set_column_bloom_filter_ndv(path, ndv);
+            }
+        }
+        if self.page_index {
+            builder = builder.set_statistics_enabled(EnabledStatistics::Page);
+        }
+        if !self.key_value_metadata.is_empty() {
+            builder = builder.set_key_value_metadata(Some(
+                self.key_value_metadata
+                    .iter()
+                    .map(|(key, value)| KeyValue::new(key.clone(), value.clone()))
+                    .collect(),
+            ));
+        }
+        let sorting_columns = self.parquet_sorting_columns(schema)?;
+        if !sorting_columns.is_empty() {
+            builder = builder.set_sorting_columns(Some(sorting_columns));
+        }
+        Ok(builder.build())
+    }
+
+    /// Add the key-value metadata of any Parquet input files when the input metadata is kept.
+    /// Metadata that was set explicitly takes precedence over the input metadata.
+    pub fn with_input_metadata(&self, input_filenames: &[&str]) -> Result<Self, Error> {
+        let mut options = self.clone();
+        if !self.keep_input_metadata {
+            return Ok(options);
+        }
+        let mut metadata: Vec<(String, String)> = vec![];
+        for filename in input_filenames {
+            if matches!(file_format(filename), Ok(FileFormat::Parquet)) {
+                for (key, value) in read_key_value_metadata(Path::new(filename))? {
+                    if !metadata.iter().any(|(k, _)| *k == key) {
+                        metadata.push((key, value));
+                    }
+                }
+            }
+        }
+        metadata.retain(|(key, _)| !self.key_value_metadata.iter().any(|(k, _)| k == key));
+        metadata.extend(self.key_value_metadata.iter().cloned());
+        options.key_value_metadata = metadata;
+        Ok(options)
+    }
+
+    /// Map the sort order to Parquet leaf column indexes. Only a prefix of the sort order can be
+    /// recorded, up to the first column that is not a primitive column in the file.
+    fn parquet_sorting_columns(&self, schema: &Schema) -> Result<Vec<SortingColumn>, Error> {
+        let descriptor = arrow_to_parquet_schema(schema)?;
+        let mut sorting_columns = vec![];
+        for sort in &self.sorting_columns {
+            let index = descriptor
+                .columns()
+                .iter()
+                .position(|c| c.path().parts() == [sort.column.clone()]);
+            match index {
+                Some(index) => sorting_columns.push(SortingColumn::new(
+                    index as i32,
+                    sort.descending,
+                    sort.nulls_first,
+                )),
+                None => break,
+            }
+        }
+        Ok(sorting_columns)
+    }
+
+    /// Check that the columns referenced by the options exist and that column encodings are
+    /// compatible with the column types
+    pub(crate) fn validate(&self, schema: &Schema) -> Result<(), Error> {
+        for column in &self.bloom_filter_columns {
+            schema.field_with_name(column)?;
+        }
+        for (column, encoding) in &self.column_encodings {
+            let data_type = schema.field_with_name(column)?.data_type();
+            let compatible = match encoding {
+                Encoding::PLAIN => true,
+                Encoding::RLE => data_type == &DataType::Boolean,
+                Encoding::DELTA_BINARY_PACKED => data_type.is_integer() || data_type.is_temporal(),
+                _ => matches!(
+                    data_type,
+                    DataType::Utf8 | DataType::LargeUtf8 | DataType::Binary | DataType::LargeBinary
+                ),
+            };
+            if !compatible {
+                return Err(Error::General(format!(
+                    "Encoding {} cannot be used for column '{}' of type {}",
+                    encoding, column, data_type
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parse a dot-separated path to a (possibly nested) column
+pub fn column_path(column: &str) -> ColumnPath {
+    ColumnPath::new(column.split('.').map(|s| s.to_string()).collect())
+}
+
+/// Parse a Parquet compression codec with an optional level, such as `snappy`, `zstd` or
+/// `zstd:8`
+pub fn parse_compression(compression: &str) -> Result<Compression, Error> {
+    let (codec, level) = match compression.split_once(':') {
+        Some((codec, level)) => (codec.to_lowercase(), Some(level)),
+        None => (compression.to_lowercase(), None),
+    };
+    match (codec.as_str(), level) {
+        ("none", None) => Ok(Compression::UNCOMPRESSED),
+        ("gzip", None) => Ok(Compression::GZIP(GzipLevel::default())),
+        ("brotli", None) => Ok(Compression::BROTLI(BrotliLevel::default())),
+        ("zstd", None) => Ok(Compression::ZSTD(ZstdLevel::default())),
+        (codec, Some(level)) => Compression::from_str(&format!("{}({})", codec, level)),
+        (codec, None) => Compression::from_str(codec),
+    }
+    .map_err(|e| Error::General(format!("Invalid compression '{}': {}", compression, e)))
+}
+
+/// Parse a column encoding in the form `column=encoding`, such as `id=delta_binary_packed`.
+/// `delta` is accepted as shorthand for `delta_binary_packed`.
+pub fn parse_column_encoding(value: &str) -> Result<(String, Encoding), Error> {
+    let (column, encoding) = value.split_once('=').ok_or_else(|| {
+        Error::General(format!(
+            "Invalid column encoding '{}', expected column=encoding",
+            value
+        ))
+    })?;
+    let encoding = match encoding.to_lowercase().replace('-', "_").as_str() {
+        "delta" => Encoding::DELTA_BINARY_PACKED,
+        other => Encoding::from_str(other)?,
+    };
+    match encoding {
+        Encoding::PLAIN
+        | Encoding::RLE
+        | Encoding::DELTA_BINARY_PACKED
+        | Encoding::DELTA_LENGTH_BYTE_ARRAY
+        | Encoding::DELTA_BYTE_ARRAY => Ok((column.to_string(), encoding)),
+        other => Err(Error::General(format!(
+            "Encoding {} is not supported by the Parquet writer",
+            other
+        ))),
+    }
+}
+
+/// A column to sort by, with its direction and the position of nulls
+#[derive(Debug, Clone, PartialEq)]
+pub struct SortColumn {
+    pub column: String,
+    pub descending: bool,
+    pub nulls_first: bool,
+}
+
+impl SortColumn {
+    fn sort_expr(&self) -> Expr {
+        ident(&self.column).sort(!self.descending, self.nulls_first)
+    }
+}
+
+/// Parse a sort column in the form `column [asc|desc] [nulls first|last]`, such as
+/// `date desc nulls last`. As in SQL, nulls sort last in ascending order and first in
+/// descending order unless specified.
+pub fn parse_sort_column(value: &str) -> Result<SortColumn, Error> {
+    let invalid = || {
+        Error::General(format!(
+            "Invalid sort column '{}', expected column [asc|desc] [nulls first|last]",
+            value
+        ))
+    };
+    let words: Vec<String> = value.split_whitespace().map(|w| w.to_lowercase()).collect();
+    let (column, mut rest) = match value.split_whitespace().next() {
+        Some(column) => (column.to_string(), &words[1..]),
+        None => return Err(invalid()),
+    };
+    let mut descending = false;
+    if let Some(direction) = rest.first() {
+        if direction == "asc" || direction == "desc" {
+            descending = direction == "desc";
+            rest = &rest[1..];
+        }
+    }
+    let nulls_first = match rest {
+        [] => descending,
+        [nulls, first] if nulls == "nulls" && first == "first" => true,
+        [nulls, last] if nulls == "nulls" && last == "last" => false,
+        _ => return Err(invalid()),
+    };
+    Ok(SortColumn {
+        column,
+        descending,
+        nulls_first,
+    })
+}
+
+/// Parse a Parquet writer version (`1` or `2`)
+pub fn parse_writer_version(version: &str) -> Result<WriterVersion, Error> {
+    match version {
+        "1" | "1.0" => Ok(WriterVersion::PARQUET_1_0),
+        "2" | "2.0" => Ok(WriterVersion::PARQUET_2_0),
+        other => Err(Error::General(format!(
+            "Invalid writer version '{}', expected 1 or 2",
+            other
+        ))),
+    }
+}
+
+/// Parse a statistics level (`none`, `chunk`, or `page`)
+pub fn parse_statistics(statistics: &str) -> Result<EnabledStatistics, Error> {
+    EnabledStatistics::from_str(&statistics.to_lowercase()).map_err(Error::General)
+}
+
+/// Options for converting a file
+#[derive(Debug, Clone, Default)]
+pub struct ConvertOptions {
+    /// Write the output to a single file
+    pub single_file: bool,
+    pub reader: ReaderOptions,
+    pub parquet: ParquetWriterOptions,
+    pub avro: AvroWriterOptions,
+    pub csv: CsvWriterOptions,
+    pub json: JsonWriterOptions,
+    /// SQL projection to apply to the input table, such as `a, b * 2 AS c`
+    pub select: Option<String>,
+    /// SQL filter to apply to the input table, such as `a > 10`
+    pub filter: Option<String>,
+    /// Columns to rename, as pairs of old and new names
+    pub renames: Vec<(String, String)>,
+    /// SQL query to run against the input table `t` instead of selecting all rows
+    pub sql: Option<String>,
+    /// Write a Hive-style directory tree partitioned by column values
+    pub partition: PartitionOptions,
+    /// Columns to sort the output by
+    pub sort_by: Vec<SortColumn>,
+    /// Limits on the size of each output file
+    pub split: FileSplitOptions,
+    /// Casts to apply before writing
+    pub cast: CastOptions,
+    /// Read the output back after writing and fail if it does not match the converted data
+    pub verify: bool,
+    /// Fail instead of warning when a column type cannot be represented exactly in the output
+    /// format
+    pub strict: bool,
+    /// Replace the output if it already exists
+    pub force: bool,
+    /// Do not show progress or print a summary of the conversion
+    pub quiet: bool,
+}
+
+/// Parse a metadata entry in the form `key=value`
+pub fn parse_key_value(value: &str) -> Result<(String, String), Error> {
+    value
+        .split_once('=')
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .ok_or_else(|| Error::General(format!("Invalid metadata '{}', expected key=value", value)))
+}
+
+/// Load key-value metadata from a JSON file containing an object, such as
+/// `{"schema_version": "3", "source": "crm"}`. Values that are not strings are written as JSON.
+pub fn load_metadata_file(path: &Path) -> Result<Vec<(String, String)>, Error> {
+    let json: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+    let map = json
+        .as_object()
+        .ok_or_else(|| Error::General("Metadata file must contain a JSON object".to_string()))?;
+    Ok(map
+        .iter()
+        .map(|(key, value)| match value {
+            serde_json::Value::String(value) => (key.clone(), value.clone()),
+            value => (key.clone(), value.to_string()),
+        })
+        .collect())
+}
+
+/// Parse a column rename in the form `old=new`
+pub fn parse_rename(value: &str) -> Result<(String, String), Error> {
+    value
+        .split_once('=')
+        .map(|(old, new)| (old.to_string(), new.to_string()))
+        .ok_or_else(|| Error::General(format!("Invalid rename '{}', expected old=new", value)))
+}
 
 pub async fn convert_files(
     ctx: &SessionContext,
     input_filename: &str,
     output_filename: &str,
-    single_file: bool,
-    zstd: bool,
+    options: &ConvertOptions,
 ) -> Result<Vec<RecordBatch>, Error> {
-    let df = register_table(ctx, "t", input_filename).await?;
-    let write_options = DataFrameWriteOptions::default().with_single_file_output(single_file);
-    let props = if zstd {
-        WriterProperties::builder()
-            .set_created_by("bdt".to_string())
-            .set_encoding(Encoding::PLAIN)
-            .set_compression(Compression::ZSTD(ZstdLevel::try_new(8)?))
-            .build()
-    } else {
-        WriterProperties::builder()
-            .set_created_by("bdt".to_string())
-            .set_encoding(Encoding::PLAIN)
-            .build()
-    };
-
-    match file_format(output_filename)? {
-        FileFormat::Avro => Err(Error::General(
-            "Conversion to Avro is not supported".to_string(),
-        )),
-        FileFormat::Csv => df
-            .write_csv(output_filename, write_options, None)
-            .await
-            .map_err(|e| e.into()),
-        FileFormat::Json => df
-            .write_json(output_filename, write_options)
-            .await
-            .map_err(|e| e.into()),
-        FileFormat::Parquet => df
-            .write_parquet(output_filename, write_options, Some(props))
-            .await
-            .map_err(|e| e.into()),
+    // the number of rows is only known in advance when all of them are written
+    let count_rows = options.sql.is_none() && options.filter.is_none();
+    let progress = Progress::start(&[input_filename], count_rows, options.quiet);
+    let df = transform(ctx, input_filename, options).await?;
+    let options = ConvertOptions {
+        parquet: options.parquet.with_input_metadata(&[input_filename])?,
+        ..options.clone()
+    };
+    write_output(df, output_filename, &progress, &options).await
+}
+
+/// Read the output back and check that it contains the same rows and values as the DataFrame
+/// that was written, failing if any were lost or changed by the conversion
+async fn verify_output(
+    df: DataFrame,
+    path: &str,
+    format: FileFormat,
+    output_filename: &str,
+    options: &ConvertOptions,
+) -> Result<(), Error> {
+    let ctx = SessionContext::new();
+    // CSV and JSON have no types for values such as decimals and dates, so they are read back
+    // with the written schema
+    let schema = Arc::new(Schema::from(df.schema()));
+    let output = match format {
+        FileFormat::Csv => {
+            let read_schema = csv_read_schema(&schema);
+            let read_options = CsvReadOptions::new()
+                .has_header(options.csv.header)
+                .delimiter(options.csv.delimiter)
+                .schema(&read_schema);
+            let file_extension = file_ending(path)?;
+            let df = ctx
+                .read_csv(path, read_options.file_extension(&file_extension))
+                .await?;
+            strip_byte_order_mark(df)?
+        }
+        FileFormat::Json => {
+            let read_schema = json_read_schema(&schema, &options.json);
+            let table = json_records_table(Path::new(path), None, Some(read_schema))?;
+            // timestamps that were written as numbers are converted back to the written unit
+            let exprs = schema
+                .fields()
+                .iter()
+                .map(|f| cast(ident(f.name()), f.data_type().clone()).alias(f.name()))
+                .collect();
+            ctx.read_table(Arc::new(table))?.select(exprs)?
+        }
+        FileFormat::Avro => {
+            // the Avro reader in DataFusion does not support all of the types that are written
+            let batches = read_avro_batches(Path::new(path), schema.clone())?;
+            ctx.read_table(Arc::new(MemTable::try_new(schema, vec![batches])?))?
+        }
+        FileFormat::Parquet => {
+            let read_options = ParquetReadOptions {
+                file_extension: "parquet",
+                ..Default::default()
+            };
+            ctx.read_parquet(path, read_options).await?
+        }
+        _ => {
+            let reader = ReaderOptions::default();
+            register_table(&ctx, "__output__", path, &reader).await?
+        }
+    };
+    match compare_dataframes(df, output, None).await? {
+        ComparisonResult::Ok => {
+            println!(
+                "Verified that {} matches the converted data",
+                output_filename
+            );
+            Ok(())
+        }
+        diff => Err(Error::General(format!(
+            "Verification of {} failed: {}",
+            output_filename, diff
+        ))),
+    }
+}
+
+/// The schema to read JSON output with, where timestamps that were written as numbers since the
+/// epoch are read in the unit they were written in
+fn json_read_schema(schema: &Schema, options: &JsonWriterOptions) -> SchemaRef {
+    let unit = match &options.timestamp {
+        TimestampFormat::Epoch(unit) => unit,
+        TimestampFormat::Iso8601 => return Arc::new(schema.clone()),
+    };
+    let fields: Vec<Field> = schema
+        .fields()
+        .iter()
+        .map(|field| match field.data_type() {
+            DataType::Timestamp(_, tz) => field
+                .as_ref()
+                .clone()
+                .with_data_type(DataType::Timestamp(unit.clone(), tz.clone())),
+            _ => field.as_ref().clone(),
+        })
+        .collect();
+    Arc::new(Schema::new(fields))
+}
+
+/// The schema to read CSV output with, where columns of types that the CSV reader cannot parse
+/// are read as text
+fn csv_read_schema(schema: &Schema) -> Schema {
+    let fields: Vec<Field> = schema
+        .fields()
+        .iter()
+        .map(|field| {
+            let data_type = field.data_type();
+            let readable = data_type.is_numeric()
+                || matches!(
+                    data_type,
+                    DataType::Boolean
+                        | DataType::Null
+                        | DataType::Utf8
+                        | DataType::Date32
+                        | DataType::Date64
+                        | DataType::Time32(_)
+                        | DataType::Time64(_)
+                        | DataType::Timestamp(_, _)
+                );
+            let data_type = if readable {
+                data_type.clone()
+            } else {
+                DataType::Utf8
+            };
+            Field::new(field.name(), data_type, true)
+        })
+        .collect();
+    Schema::new(fields)
+}
+
+/// Sort the results of a DataFrame and write them to the output path, in the format given by
+/// its file extension, reporting the rows written to `progress`
+pub async fn write_output(
+    df: DataFrame,
+    output_filename: &str,
+    progress: &Progress,
+    options: &ConvertOptions,
+) -> Result<Vec<RecordBatch>, Error> {
+    let format = file_format(output_filename)?;
+    write_output_as(df, output_filename, format, progress, options).await
+}
+
+/// Write a DataFrame to an output in the given format, for outputs whose extension does not
+/// determine the format, such as a directory without an extension
+pub async fn write_output_as(
+    mut df: DataFrame,
+    output_filename: &str,
+    format: FileFormat,
+    progress: &Progress,
+    options: &ConvertOptions,
+) -> Result<Vec<RecordBatch>, Error> {
+    let mut options = options.clone();
+    if !options.sort_by.is_empty() {
+        df = df.sort(options.sort_by.iter().map(SortColumn::sort_expr).collect())?;
+        options.parquet.sorting_columns = options.sort_by.clone();
+    }
+    if options.verify && !options.partition.columns.is_empty() {
+        return Err(Error::General(
+            "Partitioned output cannot be verified".to_string(),
+        ));
+    }
+    let lossy = lossy_mappings(&df.schema().into(), format, &options);
+    if options.strict && !lossy.is_empty() {
+        return Err(Error::General(format!(
+            "Cannot write {} without loss: {}",
+            output_filename,
+            lossy
+                .iter()
+                .map(|m| m.to_string())
+                .collect::<Vec<_>>()
+                .join("; ")
+        )));
+    }
+    for mapping in &lossy {
+        eprintln!("Warning: {}", mapping);
+    }
+
+    // write to a temporary path so that the output only appears once it is complete
+    let output = AtomicOutput::create(output_filename, options.force)?;
+    let batches = write_to_path(
+        progress.track(df.clone()).await?,
+        output.path(),
+        format,
+        &options,
+        !lossy.is_empty(),
+    )
+    .await?;
+    if options.verify {
+        verify_output(df, output.path(), format, output_filename, &options).await?;
+    }
+    output.commit()?;
+    progress.finish(Some(output_filename));
+    Ok(batches)
+}
+
+async fn write_to_path(
+    df: DataFrame,
+    path: &str,
+    format: FileFormat,
+    options: &ConvertOptions,
+    lossy: bool,
+) -> Result<Vec<RecordBatch>, Error> {
+    let parquet = &options.parquet;
+    // the Parquet writer in DataFusion does not add key-value metadata to the Arrow schema, the
+    // CSV writer does not support all of the CSV options or nested values, and the JSON writer
+    // cannot write decimals or binary values
+    let custom_writer = match format {
+        FileFormat::Parquet => !parquet.key_value_metadata.is_empty(),
+        FileFormat::Csv => !options.csv.is_default() || lossy,
+        FileFormat::Json => true,
+        FileFormat::Avro | FileFormat::Arrow => false,
+    };
+    let split = !options.partition.columns.is_empty() || options.split.is_enabled();
+    let write_options =
+        DataFrameWriteOptions::default().with_single_file_output(options.single_file);
+
+    match format {
+        FileFormat::Avro if !split => {
+            write_file(df, path, format, options).await?;
+            Ok(vec![])
+        }
+        FileFormat::Csv if !split && !custom_writer => {
+            let schema = Arc::new(df.schema().into());
+            let batches = df.write_csv(path, write_options, None).await?;
+            create_empty_output(path, format, schema, options)?;
+            Ok(batches)
+        }
+        FileFormat::Parquet if !split && !custom_writer => {
+            let schema = Arc::new(df.schema().into());
+            parquet.validate(&schema)?;
+            let writer_properties = parquet.writer_properties(&schema)?;
+            let batches = df
+                .write_parquet(path, write_options, Some(writer_properties))
+                .await?;
+            create_empty_output(path, format, schema, options)?;
+            Ok(batches)
+        }
         FileFormat::Arrow => unimplemented!(),
+        _ if custom_writer && options.single_file => {
+            write_file(df, path, format, options).await?;
+            Ok(vec![])
+        }
+        _ => {
+            write_files(df, path, format, options).await?;
+            Ok(vec![])
+        }
+    }
+}
+
+/// DataFusion does not create any files when there are no rows to write, so create an empty
+/// output instead
+fn create_empty_output(
+    path: &str,
+    format: FileFormat,
+    schema: SchemaRef,
+    options: &ConvertOptions,
+) -> Result<(), Error> {
+    if Path::new(path).exists() {
+        Ok(())
+    } else if options.single_file {
+        write_empty_file(path, format, schema, options)
+    } else {
+        std::fs::create_dir_all(path)?;
+        Ok(())
+    }
+}
+
+/// Options for converting every file in a directory
+#[derive(Debug, Clone)]
+pub struct BatchConvertOptions {
+    /// Glob pattern that input paths relative to the input directory must match, such as
+    /// `**/*.csv`
+    pub include: Option<String>,
+    /// File extension of the output format, such as `parquet`
+    pub to: String,
+    /// Maximum number of files to convert at once
+    pub jobs: usize,
+}
+
+/// Convert every file with a supported format in a directory and its subdirectories, keeping
+/// the relative paths and base names of the input files. Conversion continues past files that
+/// fail, and a summary of the results is printed at the end.
+pub async fn convert_dir(
+    ctx: &SessionContext,
+    input_dir: &Path,
+    output_dir: &Path,
+    batch: &BatchConvertOptions,
+    options: &ConvertOptions,
+) -> Result<(), Error> {
+    file_format(&format!("output.{}", batch.to))?;
+    let include = batch
+        .include
+        .as_deref()
+        .map(Pattern::new)
+        .transpose()
+        .map_err(|e| Error::General(format!("Invalid include pattern: {}", e)))?;
+    let match_options = MatchOptions {
+        require_literal_separator: true,
+        ..Default::default()
+    };
+    let inputs: Vec<(PathBuf, PathBuf)> = list_dir_files(input_dir, None)?
+        .into_iter()
+        .filter_map(|input| {
+            let relative = input.strip_prefix(input_dir).ok()?.to_path_buf();
+            let included = match &include {
+                Some(pattern) => pattern.matches_path_with(&relative, match_options),
+                None => file_format(&relative.to_string_lossy()).is_ok(),
+            };
+            included.then(|| (input, output_dir.join(relative).with_extension(&batch.to)))
+        })
+        .collect();
+
+    // leave the reporting to the summary table since several files are converted at once
+    let mut options = options.clone();
+    options.quiet = true;
+    let options = Arc::new(options);
+    // each conversion runs on its own task so that conversions that block while reading or
+    // writing files run on separate threads
+    let results: Vec<(PathBuf, PathBuf, Result<(), Error>, f64)> = futures::stream::iter(inputs)
+        .map(|(input, output)| {
+            let config = ctx.copied_config();
+            let runtime = ctx.runtime_env();
+            let options = options.clone();
+            let (task_input, task_output) = (input.clone(), output.clone());
+            let start = Instant::now();
+            let task = tokio::spawn(async move {
+                convert_file(config, runtime, &task_input, &task_output, &options).await
+            });
+            async move {
+                // a task that panics is recorded as a failure of its file like any other error
+                let result = task
+                    .await
+                    .unwrap_or_else(|e| Err(Error::General(format!("Conversion failed: {}", e))));
+                (input, output, result, start.elapsed().as_secs_f64())
+            }
+        })
+        .buffer_unordered(batch.jobs.max(1))
+        .collect()
+        .await;
+
+    let mut table = Table::new();
+    table.load_preset("||--+-++|    ++++++");
+    table.set_header(vec![
+        Cell::new("Input"),
+        Cell::new("Output"),
+        Cell::new("Result"),
+        Cell::new("Seconds"),
+    ]);
+    let mut results = results;
+    results.sort_by(|a, b| a.0.cmp(&b.0));
+    let failures = results.iter().filter(|r| r.2.is_err()).count();
+    for (input, output, result, seconds) in &results {
+        table.add_row(vec![
+            Cell::new(input.display()),
+            Cell::new(output.display()),
+            Cell::new(match result {
+                Ok(()) => "OK".to_string(),
+                Err(e) => e.to_string(),
+            }),
+            Cell::new(format!("{:.3}", seconds)),
+        ]);
+    }
+    println!("{}", table);
+    println!(
+        "Converted {} of {} files",
+        results.len() - failures,
+        results.len()
+    );
+    if failures > 0 {
+        return Err(Error::General(format!(
+            "{} of {} files failed to convert",
+            failures,
+            results.len()
+        )));
+    }
+    Ok(())
+}
+
+/// Convert one file of a batch using its own session, since each input is registered as `t`.
+/// The sessions share one runtime so that concurrent conversions share the memory limit.
+async fn convert_file(
+    config: SessionConfig,
+    runtime: Arc<RuntimeEnv>,
+    input: &Path,
+    output: &Path,
+    options: &ConvertOptions,
+) -> Result<(), Error> {
+    let ctx = SessionContext::new_with_config_rt(config, runtime);
+    let input_filename = input
+        .to_str()
+        .ok_or_else(|| Error::General("Invalid filename".to_string()))?;
+    let output_filename = output
+        .to_str()
+        .ok_or_else(|| Error::General("Invalid filename".to_string()))?;
+    if let Some(parent) = output.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    convert_files(&ctx, input_filename, output_filename, options).await?;
+    Ok(())
+}
+
+/// Register the input file as table `t` and apply the projection, filter, renames, and casts
+async fn transform(
+    ctx: &SessionContext,
+    input_filename: &str,
+    options: &ConvertOptions,
+) -> Result<DataFrame, Error> {
+    let df = register_table(ctx, "t", input_filename, &options.reader).await?;
+    let mut df = match (&options.sql, &options.select, &options.filter) {
+        (Some(sql), _, _) => ctx.sql(sql).await?,
+        (None, None, None) => df,
+        (None, select, filter) => {
+            let mut sql = format!("SELECT {} FROM t", select.as_deref().unwrap_or("*"));
+            if let Some(filter) = filter {
+                sql.push_str(&format!(" WHERE {}", filter));
+            }
+            ctx.sql(&sql).await?
+        }
+    };
+    for (old, new) in &options.renames {
+        if df.schema().field_with_unqualified_name(old).is_err() {
+            return Err(Error::General(format!(
+                "Cannot rename column '{}' because it does not exist",
+                old
+            )));
+        }
+        df = df.with_column_renamed(old, new)?;
     }
+    apply_casts(ctx, df, &options.cast).await
 }
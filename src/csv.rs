@@ -0,0 +1,178 @@
+use crate::sniff::LineTerminator;
+use crate::Error;
+use ::csv::{QuoteStyle as CsvQuoteStyle, Terminator, WriterBuilder};
+use datafusion::arrow::datatypes::Schema;
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::arrow::util::display::{ArrayFormatter, FormatOptions};
+use std::io::Write;
+
+/// When to quote fields in CSV output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QuoteStyle {
+    /// Quote every field
+    Always,
+    /// Quote fields that contain the delimiter, a quote, or a line break
+    #[default]
+    Necessary,
+    /// Never quote fields, even if this produces invalid CSV
+    Never,
+}
+
+/// Parse a quote style (`always`, `necessary`, or `never`)
+pub fn parse_quote_style(quote_style: &str) -> Result<QuoteStyle, Error> {
+    match quote_style.to_lowercase().as_str() {
+        "always" => Ok(QuoteStyle::Always),
+        "necessary" => Ok(QuoteStyle::Necessary),
+        "never" => Ok(QuoteStyle::Never),
+        other => Err(Error::General(format!(
+            "Invalid quote style '{}', expected always, necessary, or never",
+            other
+        ))),
+    }
+}
+
+/// Parse a line ending (`lf` or `crlf`)
+pub fn parse_line_ending(line_ending: &str) -> Result<LineTerminator, Error> {
+    match line_ending.to_lowercase().as_str() {
+        "lf" => Ok(LineTerminator::Lf),
+        "crlf" => Ok(LineTerminator::CrLf),
+        other => Err(Error::General(format!(
+            "Invalid line ending '{}', expected lf or crlf",
+            other
+        ))),
+    }
+}
+
+/// Parse a single-byte delimiter. `tab` and `\t` are accepted for tab-separated output.
+pub fn parse_delimiter(delimiter: &str) -> Result<u8, Error> {
+    match delimiter {
+        "tab" | "\\t" => Ok(b'\t'),
+        d if d.len() == 1 => Ok(d.as_bytes()[0]),
+        other => Err(Error::General(format!(
+            "Invalid delimiter '{}', expected a single character",
+            other
+        ))),
+    }
+}
+
+/// Options for writing CSV files
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsvWriterOptions {
+    pub delimiter: u8,
+    pub quote_style: QuoteStyle,
+    /// Write a header row with the column names
+    pub header: bool,
+    /// Text to write for null values
+    pub null: String,
+    pub line_terminator: LineTerminator,
+    /// `strftime` format for dates
+    pub date_format: Option<String>,
+    /// `strftime` format for times
+    pub time_format: Option<String>,
+    /// `strftime` format for timestamps
+    pub timestamp_format: Option<String>,
+    /// Start the file with a UTF-8 byte order mark, which some spreadsheet applications use to
+    /// detect the encoding
+    pub bom: bool,
+}
+
+impl Default for CsvWriterOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            quote_style: QuoteStyle::Necessary,
+            header: true,
+            null: String::new(),
+            line_terminator: LineTerminator::Lf,
+            date_format: None,
+            time_format: None,
+            timestamp_format: None,
+            bom: false,
+        }
+    }
+}
+
+impl CsvWriterOptions {
+    /// Whether any option differs from the defaults
+    pub fn is_default(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+/// Writes record batches to a CSV file
+pub struct CsvWriter<W: Write> {
+    writer: ::csv::Writer<W>,
+    options: CsvWriterOptions,
+}
+
+impl<W: Write> CsvWriter<W> {
+    /// Create a writer, writing the byte order mark and header row immediately so that they are
+    /// present even if there are no rows
+    pub fn try_new(
+        mut inner: W,
+        schema: &Schema,
+        options: &CsvWriterOptions,
+    ) -> Result<Self, Error> {
+        if options.bom {
+            inner.write_all(b"\xEF\xBB\xBF")?;
+        }
+        let mut writer = WriterBuilder::new()
+            .delimiter(options.delimiter)
+            .quote_style(match options.quote_style {
+                QuoteStyle::Always => CsvQuoteStyle::Always,
+                QuoteStyle::Necessary => CsvQuoteStyle::Necessary,
+                QuoteStyle::Never => CsvQuoteStyle::Never,
+            })
+            .terminator(match options.line_terminator {
+                LineTerminator::Lf => Terminator::Any(b'\n'),
+                LineTerminator::CrLf => Terminator::CRLF,
+                LineTerminator::Cr => Terminator::Any(b'\r'),
+            })
+            .from_writer(inner);
+        if options.header {
+            writer
+                .write_record(schema.fields().iter().map(|f| f.name()))
+                .map_err(csv_error)?;
+        }
+        Ok(Self {
+            writer,
+            options: options.clone(),
+        })
+    }
+
+    pub fn write(&mut self, batch: &RecordBatch) -> Result<(), Error> {
+        let format_options = FormatOptions::default()
+            .with_display_error(false)
+            .with_null(&self.options.null)
+            .with_date_format(self.options.date_format.as_deref())
+            .with_datetime_format(self.options.timestamp_format.as_deref())
+            .with_timestamp_format(self.options.timestamp_format.as_deref())
+            .with_timestamp_tz_format(self.options.timestamp_format.as_deref())
+            .with_time_format(self.options.time_format.as_deref());
+        let formatters = batch
+            .columns()
+            .iter()
+            .map(|array| ArrayFormatter::try_new(array.as_ref(), &format_options))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut record = Vec::with_capacity(formatters.len());
+        for row in 0..batch.num_rows() {
+            record.clear();
+            for formatter in &formatters {
+                record.push(formatter.value(row).try_to_string()?);
+            }
+            self.writer.write_record(&record).map_err(csv_error)?;
+        }
+        Ok(())
+    }
+
+    /// Flush the buffered output and return the underlying writer
+    pub fn into_inner(self) -> Result<W, Error> {
+        self.writer
+            .into_inner()
+            .map_err(|e| Error::IoError(e.into_error()))
+    }
+}
+
+pub(crate) fn csv_error(e: ::csv::Error) -> Error {
+    Error::General(format!("CSV error: {}", e))
+}
@@ -0,0 +1,906 @@
+use crate::utils::list_input_files;
+use crate::Error;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use datafusion::arrow::array::{Array, ArrayRef, AsArray};
+use datafusion::arrow::compute::cast;
+use datafusion::arrow::datatypes::{
+    ArrowPrimitiveType, DataType, Float16Type, Float32Type, Float64Type, Schema, SchemaRef,
+    TimeUnit, TimestampMicrosecondType, TimestampMillisecondType, TimestampNanosecondType,
+    TimestampSecondType,
+};
+use datafusion::arrow::error::ArrowError;
+use datafusion::arrow::json::reader::{infer_json_schema_from_iterator, ReaderBuilder};
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::arrow::util::display::{ArrayFormatter, FormatOptions};
+use datafusion::datasource::streaming::StreamingTable;
+use datafusion::error::DataFusionError;
+use datafusion::execution::TaskContext;
+use datafusion::physical_plan::stream::RecordBatchReceiverStream;
+use datafusion::physical_plan::streaming::PartitionStream;
+use datafusion::physical_plan::SendableRecordBatchStream;
+use serde::de::{self, DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde_json::value::RawValue;
+use serde_json::Value;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Number of records decoded into each record batch
+const BATCH_SIZE: usize = 8192;
+
+/// Maximum number of records used to infer the schema of JSON records
+const SCHEMA_INFER_MAX_RECORDS: usize = 1000;
+
+/// Determine whether a JSON file contains a single top-level array rather than newline-delimited
+/// records. For a directory, the first JSON file in the directory is checked.
+pub fn is_json_array(path: &Path) -> Result<bool, Error> {
+    let file = match list_input_files(path)?.first() {
+        Some(file) => File::open(file)?,
+        None => return Ok(false),
+    };
+    for byte in BufReader::new(file).bytes() {
+        let byte = byte?;
+        if !byte.is_ascii_whitespace() {
+            return Ok(byte == b'[');
+        }
+    }
+    Ok(false)
+}
+
+/// Create a table of the records in a JSON file, or in the JSON files of a directory, where
+/// each file contains one or more JSON documents, such as a top-level array of objects or
+/// concatenated objects.
+///
+/// When a record path such as `data.items` is provided, the records are read from that
+/// location within each document instead of from the top level. The schema is inferred from the
+/// first records unless one is provided. The records are decoded in batches as the table is
+/// scanned, so the files are never held in memory.
+pub fn json_records_table(
+    path: &Path,
+    record_path: Option<&str>,
+    schema: Option<SchemaRef>,
+) -> Result<StreamingTable, Error> {
+    let files = list_input_files(path)?;
+    let schema = match schema {
+        Some(schema) => schema,
+        None => Arc::new(infer_json_records_schema(&files, record_path)?),
+    };
+    let partition = JsonRecordsPartition {
+        schema: schema.clone(),
+        files,
+        record_path: record_path.map(|p| p.to_string()),
+    };
+    Ok(StreamingTable::try_new(schema, vec![Arc::new(partition)])?)
+}
+
+/// Infer the schema of the JSON records in the given files from the first records
+pub fn infer_json_records_schema(
+    files: &[PathBuf],
+    record_path: Option<&str>,
+) -> Result<Schema, Error> {
+    let mut records: Vec<Value> = vec![];
+    for_each_record(files, record_path, &mut |record| {
+        records.push(serde_json::from_str(record)?);
+        Ok(records.len() < SCHEMA_INFER_MAX_RECORDS)
+    })?;
+    Ok(infer_json_schema_from_iterator(
+        records.iter().map(Ok::<_, ArrowError>),
+    )?)
+}
+
+/// The records of one or more JSON files, decoded on a blocking thread when executed
+struct JsonRecordsPartition {
+    schema: SchemaRef,
+    files: Vec<PathBuf>,
+    record_path: Option<String>,
+}
+
+impl PartitionStream for JsonRecordsPartition {
+    fn schema(&self) -> &SchemaRef {
+        &self.schema
+    }
+
+    fn execute(&self, _ctx: Arc<TaskContext>) -> SendableRecordBatchStream {
+        let mut builder = RecordBatchReceiverStream::builder(self.schema.clone(), 2);
+        let tx = builder.tx();
+        let schema = self.schema.clone();
+        let files = self.files.clone();
+        let record_path = self.record_path.clone();
+        builder.spawn_blocking(move || {
+            let mut decoder = ReaderBuilder::new(schema)
+                .with_batch_size(BATCH_SIZE)
+                .build_decoder()?;
+            // the records are decoded from their original text, so that numbers keep all of
+            // their digits, such as for decimal columns. Returns false once the stream has been
+            // dropped and no more batches are needed.
+            let mut send = |records: &mut String| -> Result<bool, Error> {
+                let mut buf = records.as_bytes();
+                while !buf.is_empty() {
+                    let read = decoder.decode(buf)?;
+                    buf = &buf[read..];
+                    if read == 0 {
+                        // the decoder stops reading once it has a full batch
+                        if let Some(batch) = decoder.flush()? {
+                            if tx.blocking_send(Ok(batch)).is_err() {
+                                return Ok(false);
+                            }
+                        }
+                    }
+                }
+                records.clear();
+                match decoder.flush()? {
+                    Some(batch) => Ok(tx.blocking_send(Ok(batch)).is_ok()),
+                    None => Ok(true),
+                }
+            };
+            let mut records = String::new();
+            let mut count = 0;
+            for_each_record(&files, record_path.as_deref(), &mut |record| {
+                records.push_str(record);
+                records.push('\n');
+                count += 1;
+                if count % BATCH_SIZE == 0 {
+                    send(&mut records)
+                } else {
+                    Ok(true)
+                }
+            })
+            .and_then(|_| send(&mut records))
+            .map(|_| ())
+            .map_err(|e| DataFusionError::External(Box::new(e)))
+        });
+        builder.build()
+    }
+}
+
+/// Call `f` with the JSON text of each record in the JSON files until it returns false. Arrays
+/// at the record path are expanded into their elements and null values are skipped. Each
+/// document is parsed incrementally, so only one record is held in memory at a time.
+fn for_each_record(
+    files: &[PathBuf],
+    record_path: Option<&str>,
+    f: &mut dyn FnMut(&str) -> Result<bool, Error>,
+) -> Result<(), Error> {
+    let path: Vec<&str> = record_path
+        .unwrap_or("")
+        .split('.')
+        .filter(|k| !k.is_empty())
+        .collect();
+    let mut walk = RecordWalk {
+        record_path: record_path.unwrap_or(""),
+        f,
+        error: None,
+        stopped: false,
+    };
+    for file in files {
+        let mut reader = BufReader::new(File::open(file)?);
+        loop {
+            // skip the whitespace between documents
+            let buf = reader.fill_buf()?;
+            if buf.is_empty() {
+                break;
+            }
+            match buf.iter().position(|b| !b.is_ascii_whitespace()) {
+                Some(i) => reader.consume(i),
+                None => {
+                    let len = buf.len();
+                    reader.consume(len);
+                    continue;
+                }
+            }
+            let mut deserializer = serde_json::Deserializer::from_reader(&mut reader);
+            let result = RecordSeed {
+                path: &path,
+                walk: &mut walk,
+            }
+            .deserialize(&mut deserializer);
+            if let Some(error) = walk.error.take() {
+                return Err(error);
+            }
+            if walk.stopped {
+                return Ok(());
+            }
+            result?;
+        }
+    }
+    Ok(())
+}
+
+/// The state of a walk over the records in JSON documents. Errors raised by the callback are
+/// kept here because they cannot pass through the deserializer unchanged.
+struct RecordWalk<'a> {
+    record_path: &'a str,
+    f: &'a mut dyn FnMut(&str) -> Result<bool, Error>,
+    error: Option<Error>,
+    stopped: bool,
+}
+
+impl RecordWalk<'_> {
+    fn emit<E: de::Error>(&mut self, record: &str) -> Result<(), E> {
+        match (self.f)(record) {
+            Ok(true) => Ok(()),
+            Ok(false) => {
+                self.stopped = true;
+                Err(E::custom("stopped"))
+            }
+            Err(e) => {
+                self.error = Some(e);
+                Err(E::custom("failed to process record"))
+            }
+        }
+    }
+
+    fn not_found<E: de::Error>(&mut self, key: &str) -> E {
+        self.error = Some(Error::General(format!(
+            "JSON path '{}' not found: no value for '{}'",
+            self.record_path, key
+        )));
+        E::custom("record path not found")
+    }
+}
+
+/// Descends into a JSON value along the remaining record path, skipping over everything else,
+/// and passes the records at the end of the path to the walk
+struct RecordSeed<'a, 'b> {
+    path: &'a [&'a str],
+    walk: &'a mut RecordWalk<'b>,
+}
+
+impl RecordSeed<'_, '_> {
+    fn scalar<E: de::Error>(self, value: Value) -> Result<(), E> {
+        match self.path.first() {
+            None => self.walk.emit(&value.to_string()),
+            Some(key) => Err(self.walk.not_found(key)),
+        }
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for RecordSeed<'_, '_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for RecordSeed<'_, '_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "a JSON value")
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<(), E> {
+        match self.path.first() {
+            None => Ok(()),
+            Some(key) => Err(self.walk.not_found(key)),
+        }
+    }
+
+    fn visit_bool<E: de::Error>(self, v: bool) -> Result<(), E> {
+        self.scalar(Value::from(v))
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<(), E> {
+        self.scalar(Value::from(v))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<(), E> {
+        self.scalar(Value::from(v))
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<(), E> {
+        self.scalar(Value::from(v))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<(), E> {
+        self.scalar(Value::from(v))
+    }
+
+    fn visit_string<E: de::Error>(self, v: String) -> Result<(), E> {
+        self.scalar(Value::from(v))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        match self.path.split_first() {
+            None => {
+                while let Some(record) = seq.next_element::<Box<RawValue>>()? {
+                    if record.get() != "null" {
+                        self.walk.emit(record.get())?;
+                    }
+                }
+                Ok(())
+            }
+            Some((key, rest)) => {
+                let index = key.parse::<usize>().ok();
+                let mut found = false;
+                for i in 0.. {
+                    let element = if Some(i) == index {
+                        let seed = RecordSeed {
+                            path: rest,
+                            walk: &mut *self.walk,
+                        };
+                        seq.next_element_seed(seed)?.inspect(|_| found = true)
+                    } else {
+                        seq.next_element::<IgnoredAny>()?.map(|_| ())
+                    };
+                    if element.is_none() {
+                        break;
+                    }
+                }
+                if found {
+                    Ok(())
+                } else {
+                    Err(self.walk.not_found(key))
+                }
+            }
+        }
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        match self.path.split_first() {
+            None => {
+                // the object has already been opened, so its text is put back together from
+                // the keys and the original text of the values
+                let mut record = String::from("{");
+                while let Some(key) = map.next_key::<String>()? {
+                    let value = map.next_value::<Box<RawValue>>()?;
+                    if record.len() > 1 {
+                        record.push(',');
+                    }
+                    record.push_str(&serde_json::to_string(&key).map_err(de::Error::custom)?);
+                    record.push(':');
+                    record.push_str(value.get());
+                }
+                record.push('}');
+                self.walk.emit(&record)
+            }
+            Some((key, rest)) => {
+                let mut found = false;
+                while let Some(name) = map.next_key::<String>()? {
+                    if !found && name == *key {
+                        let seed = RecordSeed {
+                            path: rest,
+                            walk: &mut *self.walk,
+                        };
+                        map.next_value_seed(seed)?;
+                        found = true;
+                    } else {
+                        map.next_value::<IgnoredAny>()?;
+                    }
+                }
+                if found {
+                    Ok(())
+                } else {
+                    Err(self.walk.not_found(key))
+                }
+            }
+        }
+    }
+}
+
+/// The shape of JSON output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum JsonFormat {
+    /// One object per line
+    #[default]
+    NewlineDelimited,
+    /// A single array containing every object
+    Array,
+}
+
+/// How decimal values are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DecimalFormat {
+    /// A JSON number with the exact digits of the decimal
+    #[default]
+    Number,
+    /// A string, for readers that would lose precision by parsing numbers as floats
+    String,
+}
+
+/// How binary values are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BinaryFormat {
+    #[default]
+    Base64,
+    Hex,
+}
+
+/// How timestamp values are written
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum TimestampFormat {
+    /// An ISO 8601 string such as `2024-01-02T03:04:05`
+    #[default]
+    Iso8601,
+    /// A number of time units since the Unix epoch
+    Epoch(TimeUnit),
+}
+
+/// Parse a JSON output format (`ndjson` or `array`)
+pub fn parse_json_format(format: &str) -> Result<JsonFormat, Error> {
+    match format.to_lowercase().as_str() {
+        "ndjson" | "jsonl" => Ok(JsonFormat::NewlineDelimited),
+        "array" => Ok(JsonFormat::Array),
+        other => Err(Error::General(format!(
+            "Invalid JSON format '{}', expected ndjson or array",
+            other
+        ))),
+    }
+}
+
+/// Parse a decimal format (`number` or `string`)
+pub fn parse_decimal_format(format: &str) -> Result<DecimalFormat, Error> {
+    match format.to_lowercase().as_str() {
+        "number" => Ok(DecimalFormat::Number),
+        "string" => Ok(DecimalFormat::String),
+        other => Err(Error::General(format!(
+            "Invalid decimal format '{}', expected number or string",
+            other
+        ))),
+    }
+}
+
+/// Parse a binary format (`base64` or `hex`)
+pub fn parse_binary_format(format: &str) -> Result<BinaryFormat, Error> {
+    match format.to_lowercase().as_str() {
+        "base64" => Ok(BinaryFormat::Base64),
+        "hex" => Ok(BinaryFormat::Hex),
+        other => Err(Error::General(format!(
+            "Invalid binary format '{}', expected base64 or hex",
+            other
+        ))),
+    }
+}
+
+/// Parse a timestamp format (`iso8601`, `seconds`, `millis`, `micros`, or `nanos`)
+pub fn parse_timestamp_format(format: &str) -> Result<TimestampFormat, Error> {
+    match format.to_lowercase().as_str() {
+        "iso8601" | "iso" => Ok(TimestampFormat::Iso8601),
+        "seconds" => Ok(TimestampFormat::Epoch(TimeUnit::Second)),
+        "millis" => Ok(TimestampFormat::Epoch(TimeUnit::Millisecond)),
+        "micros" => Ok(TimestampFormat::Epoch(TimeUnit::Microsecond)),
+        "nanos" => Ok(TimestampFormat::Epoch(TimeUnit::Nanosecond)),
+        other => Err(Error::General(format!(
+            "Invalid timestamp format '{}', expected iso8601, seconds, millis, micros, or nanos",
+            other
+        ))),
+    }
+}
+
+/// Options for writing JSON files
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct JsonWriterOptions {
+    pub format: JsonFormat,
+    /// Indent nested values over multiple lines
+    pub pretty: bool,
+    /// Write keys with null values instead of omitting them
+    pub explicit_nulls: bool,
+    pub decimal: DecimalFormat,
+    pub binary: BinaryFormat,
+    pub timestamp: TimestampFormat,
+}
+
+/// Writes record batches to a JSON file. Structs, lists, and maps are written as nested JSON
+/// objects and arrays.
+pub struct JsonWriter<W: Write> {
+    inner: W,
+    options: JsonWriterOptions,
+    rows_written: usize,
+    buffer: String,
+}
+
+impl<W: Write> JsonWriter<W> {
+    pub fn try_new(mut inner: W, options: &JsonWriterOptions) -> Result<Self, Error> {
+        if options.format == JsonFormat::Array {
+            inner.write_all(b"[")?;
+        }
+        Ok(Self {
+            inner,
+            options: options.clone(),
+            rows_written: 0,
+            buffer: String::new(),
+        })
+    }
+
+    pub fn write(&mut self, batch: &RecordBatch) -> Result<(), Error> {
+        // dictionaries are unpacked up front rather than looked up for every value
+        let columns = batch
+            .columns()
+            .iter()
+            .map(|column| match column.data_type() {
+                DataType::Dictionary(_, value_type) => cast(column, value_type),
+                _ => Ok(column.clone()),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let schema = batch.schema();
+        let names: Vec<&str> = schema.fields().iter().map(|f| f.name().as_str()).collect();
+        let array = self.options.format == JsonFormat::Array;
+        let indent = usize::from(array);
+        for row in 0..batch.num_rows() {
+            self.buffer.clear();
+            if array && self.rows_written > 0 {
+                self.buffer.push(',');
+            }
+            if array && self.options.pretty {
+                self.buffer.push('\n');
+                push_indent(&mut self.buffer, indent);
+            }
+            let entries = names
+                .iter()
+                .zip(&columns)
+                .map(|(name, column)| (name.to_string(), column.as_ref(), row));
+            encode_object(&mut self.buffer, entries, indent, &self.options)?;
+            if !array {
+                self.buffer.push('\n');
+            }
+            self.inner.write_all(self.buffer.as_bytes())?;
+            self.rows_written += 1;
+        }
+        Ok(())
+    }
+
+    /// Finish the JSON document and return the underlying writer
+    pub fn finish(mut self) -> Result<W, Error> {
+        if self.options.format == JsonFormat::Array {
+            if self.options.pretty && self.rows_written > 0 {
+                self.inner.write_all(b"\n")?;
+            }
+            self.inner.write_all(b"]\n")?;
+        }
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+fn push_indent(out: &mut String, indent: usize) {
+    for _ in 0..indent {
+        out.push_str("  ");
+    }
+}
+
+/// Write an object from entries of a key and the array and row holding the value
+fn encode_object<'a>(
+    out: &mut String,
+    entries: impl Iterator<Item = (String, &'a dyn Array, usize)>,
+    indent: usize,
+    options: &JsonWriterOptions,
+) -> Result<(), Error> {
+    out.push('{');
+    let mut empty = true;
+    for (key, array, row) in entries {
+        if array.is_null(row) && !options.explicit_nulls {
+            continue;
+        }
+        if !empty {
+            out.push(',');
+        }
+        if options.pretty {
+            out.push('\n');
+            push_indent(out, indent + 1);
+        }
+        out.push_str(&serde_json::to_string(&key)?);
+        out.push(':');
+        if options.pretty {
+            out.push(' ');
+        }
+        encode_value(out, array, row, indent + 1, options)?;
+        empty = false;
+    }
+    if options.pretty && !empty {
+        out.push('\n');
+        push_indent(out, indent);
+    }
+    out.push('}');
+    Ok(())
+}
+
+fn encode_array(
+    out: &mut String,
+    values: &ArrayRef,
+    indent: usize,
+    options: &JsonWriterOptions,
+) -> Result<(), Error> {
+    out.push('[');
+    for row in 0..values.len() {
+        if row > 0 {
+            out.push(',');
+        }
+        if options.pretty {
+            out.push('\n');
+            push_indent(out, indent + 1);
+        }
+        encode_value(out, values.as_ref(), row, indent + 1, options)?;
+    }
+    if options.pretty && !values.is_empty() {
+        out.push('\n');
+        push_indent(out, indent);
+    }
+    out.push(']');
+    Ok(())
+}
+
+fn encode_value(
+    out: &mut String,
+    array: &dyn Array,
+    row: usize,
+    indent: usize,
+    options: &JsonWriterOptions,
+) -> Result<(), Error> {
+    if array.is_null(row) {
+        out.push_str("null");
+        return Ok(());
+    }
+    match array.data_type() {
+        DataType::Null => out.push_str("null"),
+        DataType::Boolean => out.push_str(&array.as_boolean().value(row).to_string()),
+        DataType::Int8
+        | DataType::Int16
+        | DataType::Int32
+        | DataType::Int64
+        | DataType::UInt8
+        | DataType::UInt16
+        | DataType::UInt32
+        | DataType::UInt64 => out.push_str(&format_value(array, row)?),
+        DataType::Float16 => push_float(
+            out,
+            shortest_f16(array.as_primitive::<Float16Type>().value(row)),
+        ),
+        DataType::Float32 => push_float(
+            out,
+            shortest_f32(array.as_primitive::<Float32Type>().value(row)),
+        ),
+        DataType::Float64 => push_float(out, array.as_primitive::<Float64Type>().value(row)),
+        DataType::Decimal128(_, _) | DataType::Decimal256(_, _) => match options.decimal {
+            DecimalFormat::Number => out.push_str(&format_value(array, row)?),
+            DecimalFormat::String => push_string(out, &format_value(array, row)?)?,
+        },
+        DataType::Utf8 => push_string(out, array.as_string::<i32>().value(row))?,
+        DataType::LargeUtf8 => push_string(out, array.as_string::<i64>().value(row))?,
+        DataType::Binary => push_binary(out, array.as_binary::<i32>().value(row), options)?,
+        DataType::LargeBinary => push_binary(out, array.as_binary::<i64>().value(row), options)?,
+        DataType::FixedSizeBinary(_) => {
+            push_binary(out, array.as_fixed_size_binary().value(row), options)?
+        }
+        DataType::Timestamp(unit, _) => match &options.timestamp {
+            TimestampFormat::Iso8601 => push_string(out, &format_value(array, row)?)?,
+            TimestampFormat::Epoch(target) => {
+                let value = match unit {
+                    TimeUnit::Second => array.as_primitive::<TimestampSecondType>().value(row),
+                    TimeUnit::Millisecond => {
+                        array.as_primitive::<TimestampMillisecondType>().value(row)
+                    }
+                    TimeUnit::Microsecond => {
+                        array.as_primitive::<TimestampMicrosecondType>().value(row)
+                    }
+                    TimeUnit::Nanosecond => {
+                        array.as_primitive::<TimestampNanosecondType>().value(row)
+                    }
+                };
+                let value =
+                    (value as i128 * units_per_second(target)).div_euclid(units_per_second(unit));
+                out.push_str(&value.to_string());
+            }
+        },
+        DataType::List(_) => {
+            encode_array(out, &array.as_list::<i32>().value(row), indent, options)?
+        }
+        DataType::LargeList(_) => {
+            encode_array(out, &array.as_list::<i64>().value(row), indent, options)?
+        }
+        DataType::FixedSizeList(_, _) => {
+            encode_array(out, &array.as_fixed_size_list().value(row), indent, options)?
+        }
+        DataType::Struct(fields) => {
+            let array = array.as_struct();
+            let entries = fields
+                .iter()
+                .zip(array.columns())
+                .map(|(field, column)| (field.name().to_string(), column.as_ref(), row));
+            encode_object(out, entries, indent, options)?
+        }
+        DataType::Map(_, _) => {
+            let map = array.as_map().value(row);
+            let keys = map.column(0);
+            let values = map.column(1);
+            let entries = (0..map.len())
+                .map(|i| Ok((format_value(keys.as_ref(), i)?, values.as_ref(), i)))
+                .collect::<Result<Vec<_>, Error>>()?;
+            encode_object(out, entries.into_iter(), indent, options)?
+        }
+        _ => push_string(out, &format_value(array, row)?)?,
+    }
+    Ok(())
+}
+
+pub(crate) fn units_per_second(unit: &TimeUnit) -> i128 {
+    match unit {
+        TimeUnit::Second => 1,
+        TimeUnit::Millisecond => 1_000,
+        TimeUnit::Microsecond => 1_000_000,
+        TimeUnit::Nanosecond => 1_000_000_000,
+    }
+}
+
+/// Format a value with the default Arrow display format, which is ISO 8601 for temporal types
+fn format_value(array: &dyn Array, row: usize) -> Result<String, Error> {
+    Ok(ArrayFormatter::try_new(array, &FormatOptions::default())?
+        .value(row)
+        .try_to_string()?)
+}
+
+/// Write a float, using null for values such as NaN that JSON cannot represent
+fn push_float(out: &mut String, value: f64) {
+    if value.is_finite() {
+        out.push_str(&value.to_string());
+    } else {
+        out.push_str("null");
+    }
+}
+
+/// Widen an f32 through its shortest decimal representation, so that it is written with the
+/// digits that identify it as an f32, such as `0.1` rather than `0.10000000149011612`
+fn shortest_f32(value: f32) -> f64 {
+    value.to_string().parse().unwrap_or(value as f64)
+}
+
+/// Widen an f16 through the shortest decimal representation that reads back as the same f16
+fn shortest_f16(value: <Float16Type as ArrowPrimitiveType>::Native) -> f64 {
+    (0..5)
+        .map(|precision| format!("{:.*e}", precision, value.to_f32()))
+        .find(|text| {
+            text.parse::<f32>().is_ok_and(|parsed| {
+                <Float16Type as ArrowPrimitiveType>::Native::from_f32(parsed) == value
+            })
+        })
+        .and_then(|text| text.parse().ok())
+        .unwrap_or(value.to_f64())
+}
+
+fn push_string(out: &mut String, value: &str) -> Result<(), Error> {
+    out.push_str(&serde_json::to_string(value)?);
+    Ok(())
+}
+
+fn push_binary(out: &mut String, value: &[u8], options: &JsonWriterOptions) -> Result<(), Error> {
+    let encoded = match options.binary {
+        BinaryFormat::Base64 => BASE64.encode(value),
+        BinaryFormat::Hex => value.iter().map(|b| format!("{:02x}", b)).collect(),
+    };
+    push_string(out, &encoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::arrow::array::{
+        BinaryArray, Decimal128Array, Float16Array, Float32Array, Float64Array, Int32Array,
+        ListArray, StringArray, StructArray, TimestampMillisecondArray,
+    };
+    use datafusion::arrow::datatypes::{Field, Int32Type};
+
+    type F16 = <Float16Type as ArrowPrimitiveType>::Native;
+
+    /// Encode every row of an array as a JSON value
+    fn encode(array: &dyn Array, options: &JsonWriterOptions) -> Vec<String> {
+        (0..array.len())
+            .map(|row| {
+                let mut out = String::new();
+                encode_value(&mut out, array, row, 0, options).unwrap();
+                out
+            })
+            .collect()
+    }
+
+    #[test]
+    fn shortest_float_digits() {
+        assert_eq!(shortest_f32(0.1), 0.1);
+        assert_eq!(shortest_f32(1.0e-7), 1.0e-7);
+        assert_eq!(shortest_f32(f32::MAX), 3.4028235e38);
+        assert_eq!(shortest_f16(F16::from_f32(0.1)), 0.1);
+        assert_eq!(shortest_f16(F16::from_f32(65504.0)), 65500.0);
+        assert_eq!(shortest_f16(F16::from_f32(-2.5)), -2.5);
+    }
+
+    #[test]
+    fn encode_floats() {
+        let options = JsonWriterOptions::default();
+        let f32s = Float32Array::from(vec![Some(0.1), Some(f32::NAN), None, Some(3.0)]);
+        assert_eq!(encode(&f32s, &options), ["0.1", "null", "null", "3"]);
+        let f16s = Float16Array::from(vec![F16::from_f32(0.1), F16::INFINITY]);
+        assert_eq!(encode(&f16s, &options), ["0.1", "null"]);
+        let f64s = Float64Array::from(vec![0.1 + 0.2, -0.0]);
+        assert_eq!(encode(&f64s, &options), ["0.30000000000000004", "-0"]);
+    }
+
+    #[test]
+    fn encode_decimals() {
+        let decimals = Decimal128Array::from(vec![12345, -5])
+            .with_precision_and_scale(10, 2)
+            .unwrap();
+        let options = JsonWriterOptions::default();
+        assert_eq!(encode(&decimals, &options), ["123.45", "-0.05"]);
+        let options = JsonWriterOptions {
+            decimal: DecimalFormat::String,
+            ..Default::default()
+        };
+        assert_eq!(encode(&decimals, &options), ["\"123.45\"", "\"-0.05\""]);
+    }
+
+    #[test]
+    fn encode_timestamps() {
+        let timestamps = TimestampMillisecondArray::from(vec![1704164645123, -1]);
+        let options = JsonWriterOptions::default();
+        assert_eq!(
+            encode(&timestamps, &options),
+            ["\"2024-01-02T03:04:05.123\"", "\"1969-12-31T23:59:59.999\""]
+        );
+        let options = JsonWriterOptions {
+            timestamp: TimestampFormat::Epoch(TimeUnit::Second),
+            ..Default::default()
+        };
+        assert_eq!(encode(&timestamps, &options), ["1704164645", "-1"]);
+        let options = JsonWriterOptions {
+            timestamp: TimestampFormat::Epoch(TimeUnit::Microsecond),
+            ..Default::default()
+        };
+        assert_eq!(encode(&timestamps, &options), ["1704164645123000", "-1000"]);
+    }
+
+    #[test]
+    fn encode_strings_and_binary() {
+        let options = JsonWriterOptions::default();
+        let strings = StringArray::from(vec!["say \"hi\"\n", "é"]);
+        assert_eq!(
+            encode(&strings, &options),
+            ["\"say \\\"hi\\\"\\n\"", "\"é\""]
+        );
+        let binary = BinaryArray::from(vec![&[0xde_u8, 0xad][..]]);
+        assert_eq!(encode(&binary, &options), ["\"3q0=\""]);
+        let options = JsonWriterOptions {
+            binary: BinaryFormat::Hex,
+            ..Default::default()
+        };
+        assert_eq!(encode(&binary, &options), ["\"dead\""]);
+    }
+
+    #[test]
+    fn encode_nested_values() {
+        let list = ListArray::from_iter_primitive::<Int32Type, _, _>(vec![
+            Some(vec![Some(1), None, Some(3)]),
+            Some(vec![]),
+        ]);
+        let structs = StructArray::from(vec![
+            (
+                Arc::new(Field::new("a", DataType::Int32, true)),
+                Arc::new(Int32Array::from(vec![Some(1), None])) as ArrayRef,
+            ),
+            (
+                Arc::new(Field::new("b", DataType::Utf8, true)),
+                Arc::new(StringArray::from(vec!["x", "y"])) as ArrayRef,
+            ),
+        ]);
+        let options = JsonWriterOptions::default();
+        assert_eq!(encode(&list, &options), ["[1,null,3]", "[]"]);
+        assert_eq!(
+            encode(&structs, &options),
+            ["{\"a\":1,\"b\":\"x\"}", "{\"b\":\"y\"}"]
+        );
+        let options = JsonWriterOptions {
+            explicit_nulls: true,
+            ..Default::default()
+        };
+        assert_eq!(encode(&structs, &options)[1], "{\"a\":null,\"b\":\"y\"}");
+        let options = JsonWriterOptions {
+            pretty: true,
+            ..Default::default()
+        };
+        assert_eq!(encode(&list, &options)[0], "[\n  1,\n  null,\n  3\n]");
+        assert_eq!(
+            encode(&structs, &options)[0],
+            "{\n  \"a\": 1,\n  \"b\": \"x\"\n}"
+        );
+    }
+}
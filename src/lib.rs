@@ -1,24 +1,43 @@
+use datafusion::arrow::error::ArrowError;
 use datafusion::error::DataFusionError;
 use datafusion::parquet::errors::ParquetError;
 
+pub mod avro;
+pub mod cast;
 pub mod compare;
 pub mod convert;
+pub mod csv;
+pub mod json;
+pub mod mapping;
+pub mod merge;
 pub mod parquet;
+pub mod partition;
+pub mod progress;
+pub mod rewrite;
+pub mod schema;
+pub mod sniff;
 pub mod utils;
+pub mod writer;
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("{0}")]
     General(String),
+    #[error("Avro error: {0}")]
+    Avro(#[from] apache_avro::Error),
+    #[error("Arrow error: {0}")]
+    Arrow(#[from] ArrowError),
     #[error("Data Fusion error: {0}")]
     DataFusion(#[from] DataFusionError),
     #[error("Parquet error: {0}")]
     Parquet(#[from] ParquetError),
     #[error("I/O error: {0}")]
     IoError(#[from] std::io::Error),
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum FileFormat {
     Arrow,
     Avro,
@@ -0,0 +1,185 @@
+use crate::convert::ConvertOptions;
+use crate::json::{units_per_second, TimestampFormat};
+use crate::FileFormat;
+use datafusion::arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+use std::fmt::{Display, Formatter};
+
+/// A column with a type that the output format cannot represent exactly
+#[derive(Debug, Clone, PartialEq)]
+pub struct LossyMapping {
+    /// Path to the column, such as `address.city` for a nested field
+    pub column: String,
+    pub data_type: DataType,
+    /// What is lost when the column is written
+    pub reason: String,
+}
+
+impl Display for LossyMapping {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "column '{}' ({}): {}",
+            self.column,
+            type_name(&self.data_type),
+            self.reason
+        )
+    }
+}
+
+/// A short name for a type, without the field details of nested types
+fn type_name(data_type: &DataType) -> String {
+    match data_type {
+        DataType::Struct(_) => "Struct".to_string(),
+        DataType::Map(_, _) => "Map".to_string(),
+        DataType::List(field) => format!("List({})", type_name(field.data_type())),
+        DataType::LargeList(field) => format!("LargeList({})", type_name(field.data_type())),
+        DataType::FixedSizeList(field, size) => {
+            format!("FixedSizeList({}, {})", type_name(field.data_type()), size)
+        }
+        other => other.to_string(),
+    }
+}
+
+/// Find the columns of a schema that would lose values or type information when written in
+/// the output format, such as nested columns written to CSV or nanosecond timestamps written to
+/// Avro
+pub fn lossy_mappings(
+    schema: &Schema,
+    format: FileFormat,
+    options: &ConvertOptions,
+) -> Vec<LossyMapping> {
+    let mut mappings = vec![];
+    for field in schema.fields() {
+        check_field(field, field.name(), format, options, &mut mappings);
+    }
+    mappings
+}
+
+fn check_field(
+    field: &Field,
+    path: &str,
+    format: FileFormat,
+    options: &ConvertOptions,
+    mappings: &mut Vec<LossyMapping>,
+) {
+    let data_type = field.data_type();
+    let reason = match format {
+        FileFormat::Csv => csv_loss(data_type),
+        FileFormat::Json => json_loss(data_type, options),
+        FileFormat::Avro => avro_loss(data_type),
+        FileFormat::Parquet => parquet_loss(data_type),
+        FileFormat::Arrow => None,
+    };
+    if let Some(reason) = reason {
+        mappings.push(LossyMapping {
+            column: path.to_string(),
+            data_type: data_type.clone(),
+            reason,
+        });
+    }
+    // CSV writes nested values as text, so there is nothing more to check inside them
+    if format == FileFormat::Csv {
+        return;
+    }
+    match data_type {
+        DataType::Struct(fields) => {
+            for child in fields {
+                let path = format!("{}.{}", path, child.name());
+                check_field(child, &path, format, options, mappings);
+            }
+        }
+        DataType::List(child) | DataType::LargeList(child) | DataType::FixedSizeList(child, _) => {
+            check_field(child, &format!("{}[]", path), format, options, mappings);
+        }
+        DataType::Map(entries, _) => {
+            if let DataType::Struct(fields) = entries.data_type() {
+                for child in fields {
+                    let path = format!("{}.{}", path, child.name());
+                    check_field(child, &path, format, options, mappings);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn csv_loss(data_type: &DataType) -> Option<String> {
+    match data_type {
+        DataType::Struct(_)
+        | DataType::List(_)
+        | DataType::LargeList(_)
+        | DataType::FixedSizeList(_, _)
+        | DataType::Map(_, _) => Some("nested values are written to CSV as text".to_string()),
+        DataType::Binary | DataType::LargeBinary | DataType::FixedSizeBinary(_) => {
+            Some("binary values are written to CSV as hex text".to_string())
+        }
+        DataType::Dictionary(_, _) => Some(dictionary_loss("CSV")),
+        _ => None,
+    }
+}
+
+fn json_loss(data_type: &DataType, options: &ConvertOptions) -> Option<String> {
+    match (data_type, &options.json.timestamp) {
+        (DataType::Timestamp(unit, _), TimestampFormat::Epoch(target))
+            if units_per_second(target) < units_per_second(unit) =>
+        {
+            Some(format!(
+                "timestamps are truncated to {} in JSON",
+                unit_name(target)
+            ))
+        }
+        (DataType::Map(entries, _), _) => match entries.data_type() {
+            DataType::Struct(fields)
+                if !matches!(fields[0].data_type(), DataType::Utf8 | DataType::LargeUtf8) =>
+            {
+                Some("map keys are written to JSON as strings".to_string())
+            }
+            _ => None,
+        },
+        (DataType::Dictionary(_, _), _) => Some(dictionary_loss("JSON")),
+        _ => None,
+    }
+}
+
+fn avro_loss(data_type: &DataType) -> Option<String> {
+    match data_type {
+        // smaller unsigned integers fit in the next larger signed Avro type
+        DataType::UInt64 => Some(
+            "UInt64 values are written to Avro as signed long, which cannot hold values above \
+             9223372036854775807"
+                .to_string(),
+        ),
+        DataType::Float16 => Some("Float16 values are written to Avro as float".to_string()),
+        DataType::Timestamp(TimeUnit::Nanosecond, _) | DataType::Time64(TimeUnit::Nanosecond) => {
+            Some("nanoseconds are truncated to microseconds in Avro".to_string())
+        }
+        DataType::Date64 => Some("dates are written to Avro as days, without a time".to_string()),
+        DataType::Dictionary(_, _) => Some(dictionary_loss("Avro")),
+        _ => None,
+    }
+}
+
+fn parquet_loss(data_type: &DataType) -> Option<String> {
+    match data_type {
+        DataType::Date64 => {
+            Some("dates are written to Parquet as days, without a time".to_string())
+        }
+        _ => None,
+    }
+}
+
+fn dictionary_loss(format: &str) -> String {
+    format!(
+        "dictionary encoding is not preserved in {}, only the values are written",
+        format
+    )
+}
+
+fn unit_name(unit: &TimeUnit) -> &'static str {
+    match unit {
+        TimeUnit::Second => "seconds",
+        TimeUnit::Millisecond => "milliseconds",
+        TimeUnit::Microsecond => "microseconds",
+        TimeUnit::Nanosecond => "nanoseconds",
+    }
+}
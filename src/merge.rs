@@ -0,0 +1,321 @@
+use crate::convert::{write_output, ConvertOptions};
+use crate::progress::Progress;
+use crate::utils::register_table;
+use crate::Error;
+use datafusion::arrow::datatypes::DataType;
+use datafusion::common::ScalarValue;
+use datafusion::prelude::{cast, ident, lit, DataFrame, SessionContext};
+
+/// Merge files of any supported format into one output by unioning their rows by column name.
+///
+/// Columns that are missing from some inputs are filled with nulls, and columns with different
+/// types are widened when a type can hold the values of both, such as `Int32` and `Int64` to
+/// `Int64`. Columns with conflicting types are reported as an error. The merged rows can
+/// optionally be de-duplicated before being sorted and written with the convert options.
+pub async fn merge_files(
+    ctx: &SessionContext,
+    input_filenames: &[&str],
+    output_filename: &str,
+    distinct: bool,
+    options: &ConvertOptions,
+) -> Result<(), Error> {
+    // distinct rows are not known in advance
+    let progress = Progress::start(input_filenames, !distinct, options.quiet);
+    let mut inputs = vec![];
+    for (i, filename) in input_filenames.iter().enumerate() {
+        let table_name = format!("t{}", i + 1);
+        let df = register_table(ctx, &table_name, filename, &options.reader).await?;
+        inputs.push((*filename, df));
+    }
+
+    // merged columns in the order they first appear, with the type from each input
+    let mut columns: Vec<(String, Vec<(&str, DataType)>)> = vec![];
+    for (filename, df) in &inputs {
+        for field in df.schema().fields() {
+            let data_type = (*filename, field.data_type().clone());
+            match columns.iter_mut().find(|(name, _)| name == field.name()) {
+                Some((_, types)) => types.push(data_type),
+                None => columns.push((field.name().to_string(), vec![data_type])),
+            }
+        }
+    }
+    let mut conflicts = vec![];
+    let mut merged_types = vec![];
+    for (name, types) in &columns {
+        match types
+            .iter()
+            .try_fold(DataType::Null, |a, (_, b)| widen_type(&a, b))
+        {
+            Some(data_type) => merged_types.push(data_type),
+            None => conflicts.push(format!(
+                "'{}' ({})",
+                name,
+                types
+                    .iter()
+                    .map(|(filename, data_type)| format!("{} in {}", data_type, filename))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )),
+        }
+    }
+    if !conflicts.is_empty() {
+        return Err(Error::General(format!(
+            "Cannot merge columns with conflicting types: {}",
+            conflicts.join("; ")
+        )));
+    }
+
+    let mut merged: Option<DataFrame> = None;
+    for (_, df) in inputs {
+        let exprs = columns
+            .iter()
+            .zip(&merged_types)
+            .map(|((name, _), data_type)| {
+                let expr = if df.schema().field_with_unqualified_name(name).is_ok() {
+                    ident(name)
+                } else {
+                    lit(ScalarValue::Null)
+                };
+                cast(expr, data_type.clone()).alias(name)
+            })
+            .collect();
+        let df = df.select(exprs)?;
+        merged = Some(match merged {
+            Some(merged) => merged.union(df)?,
+            None => df,
+        });
+    }
+    let mut df = merged.ok_or_else(|| Error::General("No files to merge".to_string()))?;
+    if distinct {
+        df = df.distinct()?;
+    }
+    let options = ConvertOptions {
+        parquet: options.parquet.with_input_metadata(input_filenames)?,
+        ..options.clone()
+    };
+    write_output(df, output_filename, &progress, &options).await?;
+    Ok(())
+}
+
+/// Integer types in the order they are tried when widening
+const INTEGER_TYPES: [DataType; 8] = [
+    DataType::Int8,
+    DataType::UInt8,
+    DataType::Int16,
+    DataType::UInt16,
+    DataType::Int32,
+    DataType::UInt32,
+    DataType::Int64,
+    DataType::UInt64,
+];
+
+/// Float types in the order they are tried when widening
+const FLOAT_TYPES: [DataType; 3] = [DataType::Float16, DataType::Float32, DataType::Float64];
+
+/// Find a type that can hold values of both types without loss, such as `Int64` for `Int32`
+/// and `Int64`, or `Float64` for `Int32` and `Float32`. Types without such a type, such as
+/// `Int64` and `Float64` or `Int64` and `UInt64`, are conflicts. Numbers are not widened to
+/// strings.
+fn widen_type(a: &DataType, b: &DataType) -> Option<DataType> {
+    match (a, b) {
+        _ if a == b => Some(a.clone()),
+        (DataType::Null, other) | (other, DataType::Null) => Some(other.clone()),
+        _ if a.is_numeric() && b.is_numeric() => widen_numeric(a, b),
+        (DataType::Utf8 | DataType::LargeUtf8, DataType::Utf8 | DataType::LargeUtf8) => {
+            Some(DataType::LargeUtf8)
+        }
+        (DataType::Date32 | DataType::Date64, DataType::Date32 | DataType::Date64) => {
+            Some(DataType::Date64)
+        }
+        (DataType::Date32 | DataType::Date64, DataType::Timestamp(_, _)) => Some(b.clone()),
+        (DataType::Timestamp(_, _), DataType::Date32 | DataType::Date64) => Some(a.clone()),
+        (DataType::Timestamp(unit1, tz1), DataType::Timestamp(unit2, tz2)) if tz1 == tz2 => {
+            // the finer unit holds the values of the coarser one
+            Some(DataType::Timestamp(unit1.max(unit2).clone(), tz1.clone()))
+        }
+        _ => None,
+    }
+}
+
+fn widen_numeric(a: &DataType, b: &DataType) -> Option<DataType> {
+    if a.is_integer() && b.is_integer() {
+        let (a, b) = (integer_range(a)?, integer_range(b)?);
+        return INTEGER_TYPES.into_iter().find(|t| {
+            integer_range(t).is_some_and(|r| r.0 <= a.0.min(b.0) && r.1 >= a.1.max(b.1))
+        });
+    }
+    if a.is_floating() || b.is_floating() {
+        return FLOAT_TYPES
+            .into_iter()
+            .find(|t| holds_exactly(t, a) && holds_exactly(t, b));
+    }
+    // decimals keep the larger number of digits on both sides of the decimal point
+    let ((precision1, scale1), (precision2, scale2)) = (decimal_digits(a)?, decimal_digits(b)?);
+    let scale = scale1.max(scale2);
+    let precision = (precision1 - scale1).max(precision2 - scale2) + scale;
+    let decimal256 =
+        matches!(a, DataType::Decimal256(_, _)) || matches!(b, DataType::Decimal256(_, _));
+    if precision <= 38 && !decimal256 {
+        Some(DataType::Decimal128(precision as u8, scale as i8))
+    } else if precision <= 76 {
+        Some(DataType::Decimal256(precision as u8, scale as i8))
+    } else {
+        None
+    }
+}
+
+/// The smallest and largest values of an integer type
+fn integer_range(data_type: &DataType) -> Option<(i128, i128)> {
+    Some(match data_type {
+        DataType::Int8 => (i8::MIN as i128, i8::MAX as i128),
+        DataType::Int16 => (i16::MIN as i128, i16::MAX as i128),
+        DataType::Int32 => (i32::MIN as i128, i32::MAX as i128),
+        DataType::Int64 => (i64::MIN as i128, i64::MAX as i128),
+        DataType::UInt8 => (0, u8::MAX as i128),
+        DataType::UInt16 => (0, u16::MAX as i128),
+        DataType::UInt32 => (0, u32::MAX as i128),
+        DataType::UInt64 => (0, u64::MAX as i128),
+        _ => return None,
+    })
+}
+
+/// Whether a float type can represent every value of another numeric type exactly
+fn holds_exactly(float_type: &DataType, data_type: &DataType) -> bool {
+    let mantissa_bits = |t: &DataType| match t {
+        DataType::Float16 => Some(11),
+        DataType::Float32 => Some(24),
+        DataType::Float64 => Some(53),
+        _ => None,
+    };
+    let Some(bits) = mantissa_bits(float_type) else {
+        return false;
+    };
+    match (mantissa_bits(data_type), integer_range(data_type)) {
+        (Some(other_bits), _) => other_bits <= bits,
+        (_, Some((min, max))) => min.unsigned_abs().max(max.unsigned_abs()) <= 1 << bits,
+        _ => false,
+    }
+}
+
+/// The precision and scale of a decimal type, or of the decimal that holds an integer type
+fn decimal_digits(data_type: &DataType) -> Option<(i16, i16)> {
+    match data_type {
+        DataType::Decimal128(precision, scale) | DataType::Decimal256(precision, scale) => {
+            Some((*precision as i16, *scale as i16))
+        }
+        other => {
+            let (min, max) = integer_range(other)?;
+            let digits = min.unsigned_abs().max(max.unsigned_abs()).to_string().len();
+            Some((digits as i16, 0))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::arrow::datatypes::TimeUnit;
+
+    #[test]
+    fn widen_integers() {
+        let cases = [
+            (DataType::Int32, DataType::Int64, Some(DataType::Int64)),
+            (DataType::UInt8, DataType::Int8, Some(DataType::Int16)),
+            (DataType::UInt32, DataType::Int32, Some(DataType::Int64)),
+            (DataType::UInt64, DataType::Int64, None),
+            (DataType::Null, DataType::UInt16, Some(DataType::UInt16)),
+        ];
+        for (a, b, expected) in cases {
+            assert_eq!(widen_type(&a, &b), expected, "{} and {}", a, b);
+            assert_eq!(widen_type(&b, &a), expected, "{} and {}", b, a);
+        }
+    }
+
+    #[test]
+    fn widen_floats_only_when_exact() {
+        let cases = [
+            (DataType::Int16, DataType::Float32, Some(DataType::Float32)),
+            (DataType::Int32, DataType::Float32, Some(DataType::Float64)),
+            (
+                DataType::Float16,
+                DataType::Float32,
+                Some(DataType::Float32),
+            ),
+            (DataType::Int64, DataType::Float64, None),
+            (DataType::Decimal128(10, 2), DataType::Float64, None),
+        ];
+        for (a, b, expected) in cases {
+            assert_eq!(widen_type(&a, &b), expected, "{} and {}", a, b);
+            assert_eq!(widen_type(&b, &a), expected, "{} and {}", b, a);
+        }
+    }
+
+    #[test]
+    fn widen_decimals() {
+        let cases = [
+            (
+                DataType::Decimal128(10, 2),
+                DataType::Decimal128(5, 4),
+                Some(DataType::Decimal128(12, 4)),
+            ),
+            (
+                DataType::Decimal128(10, 2),
+                DataType::Int32,
+                Some(DataType::Decimal128(12, 2)),
+            ),
+            (
+                DataType::Decimal128(38, 0),
+                DataType::Decimal128(38, 10),
+                Some(DataType::Decimal256(48, 10)),
+            ),
+            (
+                DataType::Decimal256(10, 2),
+                DataType::Decimal128(10, 2),
+                Some(DataType::Decimal256(10, 2)),
+            ),
+            (
+                DataType::Decimal256(76, 0),
+                DataType::Decimal128(10, 10),
+                None,
+            ),
+        ];
+        for (a, b, expected) in cases {
+            assert_eq!(widen_type(&a, &b), expected, "{} and {}", a, b);
+            assert_eq!(widen_type(&b, &a), expected, "{} and {}", b, a);
+        }
+    }
+
+    #[test]
+    fn widen_strings_and_temporal_types() {
+        let utc = Some("UTC".into());
+        let cases = [
+            (
+                DataType::Utf8,
+                DataType::LargeUtf8,
+                Some(DataType::LargeUtf8),
+            ),
+            (DataType::Date32, DataType::Date64, Some(DataType::Date64)),
+            (
+                DataType::Date32,
+                DataType::Timestamp(TimeUnit::Millisecond, None),
+                Some(DataType::Timestamp(TimeUnit::Millisecond, None)),
+            ),
+            (
+                DataType::Timestamp(TimeUnit::Second, utc.clone()),
+                DataType::Timestamp(TimeUnit::Nanosecond, utc.clone()),
+                Some(DataType::Timestamp(TimeUnit::Nanosecond, utc.clone())),
+            ),
+            (
+                DataType::Timestamp(TimeUnit::Second, utc),
+                DataType::Timestamp(TimeUnit::Second, None),
+                None,
+            ),
+            (DataType::Utf8, DataType::Int32, None),
+            (DataType::Boolean, DataType::Int8, None),
+        ];
+        for (a, b, expected) in cases {
+            assert_eq!(widen_type(&a, &b), expected, "{} and {}", a, b);
+            assert_eq!(widen_type(&b, &a), expected, "{} and {}", b, a);
+        }
+    }
+}
@@ -1,141 +1,854 @@
+use crate::csv::csv_error;
 use crate::Error;
 use comfy_table::{Cell, Table};
-use datafusion::parquet::basic::LogicalType;
+use datafusion::parquet::arrow::ARROW_SCHEMA_META_KEY;
+use datafusion::parquet::basic::{Compression, Encoding, LogicalType, PageType, Type};
+use datafusion::parquet::data_type::{ByteArray, FixedLenByteArray};
+use datafusion::parquet::file::metadata::ColumnChunkMetaData;
+use datafusion::parquet::file::page_index::index::{Index, PageIndex};
+use datafusion::parquet::file::properties::ReaderProperties;
 use datafusion::parquet::file::reader::{FileReader, SerializedFileReader};
+use datafusion::parquet::file::serialized_reader::ReadOptionsBuilder;
 use datafusion::parquet::file::statistics::Statistics;
+use datafusion::parquet::format::PageHeader;
+use datafusion::parquet::thrift::TSerializable;
+use serde_json::json;
 use std::fs::File;
-use std::path::PathBuf;
+use std::io::{BufReader, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use thrift::protocol::TCompactInputProtocol;
 
-pub fn view_parquet_meta(path: PathBuf) -> Result<(), Error> {
+/// Output format for metadata reports
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ReportFormat {
+    /// Tables for reading in a terminal
+    #[default]
+    Table,
+    /// A JSON document
+    Json,
+    /// CSV with one row per column chunk
+    Csv,
+}
+
+/// Parse a report format (table, json, or csv)
+pub fn parse_report_format(format: &str) -> Result<ReportFormat, Error> {
+    match format.to_lowercase().as_str() {
+        "table" => Ok(ReportFormat::Table),
+        "json" => Ok(ReportFormat::Json),
+        "csv" => Ok(ReportFormat::Csv),
+        _ => Err(Error::General(format!(
+            "Invalid format '{}', expected table, json, or csv",
+            format
+        ))),
+    }
+}
+
+/// The footer metadata of a Parquet file
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParquetMetadataReport {
+    pub version: i32,
+    pub created_by: Option<String>,
+    pub num_rows: i64,
+    /// Key-value metadata, except for the serialized Arrow schema
+    pub key_value_metadata: Vec<(String, String)>,
+    pub row_groups: Vec<RowGroupReport>,
+}
+
+/// The metadata of one row group of a Parquet file
+#[derive(Debug, Clone, PartialEq)]
+pub struct RowGroupReport {
+    pub num_rows: i64,
+    pub total_byte_size: i64,
+    /// Sort order of the rows, such as `id asc nulls first`
+    pub sorting_columns: Vec<String>,
+    pub columns: Vec<ColumnChunkReport>,
+}
+
+/// The metadata and statistics of one column in a row group
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnChunkReport {
+    /// Dot-separated path to the column
+    pub name: String,
+    pub logical_type: Option<String>,
+    pub physical_type: String,
+    pub distinct_count: Option<u64>,
+    pub null_count: Option<u64>,
+    pub min: Option<String>,
+    pub max: Option<String>,
+    /// Encodings used by the pages of the column chunk, such as `PLAIN` and `RLE_DICTIONARY`
+    pub encodings: Vec<String>,
+    /// Compression codec, such as `SNAPPY` or `ZSTD`
+    pub compression: String,
+    /// Number of values, including nulls
+    pub num_values: i64,
+    pub compressed_size: i64,
+    pub uncompressed_size: i64,
+    pub dictionary_page_offset: Option<i64>,
+    pub data_page_offset: i64,
+}
+
+impl ColumnChunkReport {
+    /// The uncompressed size divided by the compressed size
+    pub fn compression_ratio(&self) -> Option<f64> {
+        compression_ratio(self.uncompressed_size, self.compressed_size)
+    }
+}
+
+/// The sizes of one column summed across all row groups
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnTotalReport {
+    pub name: String,
+    /// Encodings used by any of the column chunks
+    pub encodings: Vec<String>,
+    /// Compression codecs used by any of the column chunks
+    pub compression: Vec<String>,
+    pub num_values: i64,
+    /// Number of nulls, if all column chunks have statistics
+    pub null_count: Option<u64>,
+    pub compressed_size: i64,
+    pub uncompressed_size: i64,
+}
+
+impl ColumnTotalReport {
+    /// The uncompressed size divided by the compressed size
+    pub fn compression_ratio(&self) -> Option<f64> {
+        compression_ratio(self.uncompressed_size, self.compressed_size)
+    }
+}
+
+fn compression_ratio(uncompressed_size: i64, compressed_size: i64) -> Option<f64> {
+    (compressed_size > 0).then(|| uncompressed_size as f64 / compressed_size as f64)
+}
+
+/// The name of a compression codec, without the compression level
+fn codec_name(compression: Compression) -> &'static str {
+    match compression {
+        Compression::UNCOMPRESSED => "UNCOMPRESSED",
+        Compression::SNAPPY => "SNAPPY",
+        Compression::GZIP(_) => "GZIP",
+        Compression::LZO => "LZO",
+        Compression::BROTLI(_) => "BROTLI",
+        Compression::LZ4 => "LZ4",
+        Compression::ZSTD(_) => "ZSTD",
+        Compression::LZ4_RAW => "LZ4_RAW",
+    }
+}
+
+/// Read the footer metadata of a Parquet file
+pub fn parquet_metadata_report(path: &Path) -> Result<ParquetMetadataReport, Error> {
+    let reader = SerializedFileReader::new(File::open(path)?)?;
+    let parquet_metadata = reader.metadata();
+    let file_meta = parquet_metadata.file_metadata();
+    let key_value_metadata = file_meta
+        .key_value_metadata()
+        .into_iter()
+        .flatten()
+        .filter(|kv| kv.key != ARROW_SCHEMA_META_KEY)
+        .map(|kv| (kv.key.clone(), kv.value.clone().unwrap_or_default()))
+        .collect();
+
+    let mut row_groups = vec![];
+    for md in parquet_metadata.row_groups() {
+        let sorting_columns = md
+            .sorting_columns()
+            .into_iter()
+            .flatten()
+            .map(|c| {
+                format!(
+                    "{} {} nulls {}",
+                    md.column(c.column_idx as usize).column_path().string(),
+                    if c.descending { "desc" } else { "asc" },
+                    if c.nulls_first { "first" } else { "last" }
+                )
+            })
+            .collect();
+        let columns = md.columns().iter().map(column_chunk_report).collect();
+        row_groups.push(RowGroupReport {
+            num_rows: md.num_rows(),
+            total_byte_size: md.total_byte_size(),
+            sorting_columns,
+            columns,
+        });
+    }
+    Ok(ParquetMetadataReport {
+        version: file_meta.version(),
+        created_by: file_meta.created_by().map(|s| s.to_string()),
+        num_rows: file_meta.num_rows(),
+        key_value_metadata,
+        row_groups,
+    })
+}
+
+fn column_chunk_report(column: &ColumnChunkMetaData) -> ColumnChunkReport {
+    let descr = column.column_descr();
+    let stats = column.statistics();
+    let min_max = match stats.filter(|stats| stats.has_min_max_set()) {
+        Some(Statistics::Boolean(v)) => Some((v.min().to_string(), v.max().to_string())),
+        Some(Statistics::Int32(v)) => Some((v.min().to_string(), v.max().to_string())),
+        Some(Statistics::Int64(v)) => Some((v.min().to_string(), v.max().to_string())),
+        Some(Statistics::Float(v)) => Some((v.min().to_string(), v.max().to_string())),
+        Some(Statistics::Double(v)) => Some((v.min().to_string(), v.max().to_string())),
+        Some(Statistics::ByteArray(v)) => match descr.logical_type() {
+            Some(LogicalType::String) => Some((
+                v.min().as_utf8().unwrap().to_string(),
+                v.max().as_utf8().unwrap().to_string(),
+            )),
+            _ => Some((v.min().to_string(), v.max().to_string())),
+        },
+        Some(_) => Some(("unsupported".to_string(), "unsupported".to_string())),
+        None => None,
+    };
+    let (min, max) = min_max.unzip();
+    ColumnChunkReport {
+        name: column.column_path().string(),
+        logical_type: descr.logical_type().map(|t| format!("{:?}", t)),
+        physical_type: descr.physical_type().to_string(),
+        distinct_count: stats.and_then(|stats| stats.distinct_count()),
+        null_count: stats.map(|stats| stats.null_count()),
+        min,
+        max,
+        encodings: column.encodings().iter().map(|e| e.to_string()).collect(),
+        compression: codec_name(column.compression()).to_string(),
+        num_values: column.num_values(),
+        compressed_size: column.compressed_size(),
+        uncompressed_size: column.uncompressed_size(),
+        dictionary_page_offset: column.dictionary_page_offset(),
+        data_page_offset: column.data_page_offset(),
+    }
+}
+
+impl ParquetMetadataReport {
+    /// Sum the sizes of each column across all row groups
+    pub fn column_totals(&self) -> Vec<ColumnTotalReport> {
+        let mut totals: Vec<ColumnTotalReport> = vec![];
+        for column in self
+            .row_groups
+            .iter()
+            .flat_map(|row_group| &row_group.columns)
+        {
+            let total = match totals.iter_mut().find(|t| t.name == column.name) {
+                Some(total) => total,
+                None => {
+                    totals.push(ColumnTotalReport {
+                        name: column.name.clone(),
+                        encodings: vec![],
+                        compression: vec![],
+                        num_values: 0,
+                        null_count: Some(0),
+                        compressed_size: 0,
+                        uncompressed_size: 0,
+                    });
+                    totals.last_mut().unwrap()
+                }
+            };
+            for encoding in &column.encodings {
+                if !total.encodings.contains(encoding) {
+                    total.encodings.push(encoding.clone());
+                }
+            }
+            if !total.compression.contains(&column.compression) {
+                total.compression.push(column.compression.clone());
+            }
+            total.num_values += column.num_values;
+            total.null_count = total.null_count.zip(column.null_count).map(|(a, b)| a + b);
+            total.compressed_size += column.compressed_size;
+            total.uncompressed_size += column.uncompressed_size;
+        }
+        totals
+    }
+
+    /// Render the report as a table of file metadata followed by a table for each row group and
+    /// a table of column totals
+    pub fn to_table_string(&self) -> String {
+        let mut table = Table::new();
+        table.load_preset("||--+-++|    ++++++");
+        table.set_header(vec![Cell::new("Key"), Cell::new("Value")]);
+        table.add_row(vec![Cell::new("Version"), Cell::new(self.version)]);
+        table.add_row(vec![
+            Cell::new("Created By"),
+            Cell::new(self.created_by.as_deref().unwrap_or("N/A")),
+        ]);
+        table.add_row(vec![Cell::new("Rows"), Cell::new(self.num_rows)]);
+        table.add_row(vec![
+            Cell::new("Row Groups"),
+            Cell::new(self.row_groups.len()),
+        ]);
+        for (key, value) in &self.key_value_metadata {
+            table.add_row(vec![
+                Cell::new(format!("Metadata: {}", key)),
+                Cell::new(value),
+            ]);
+        }
+        let mut output = table.to_string();
+
+        for (i, row_group) in self.row_groups.iter().enumerate() {
+            output.push_str(&format!(
+                "\n\nRow Group {} of {} contains {} rows and has {} bytes:\n\n",
+                i,
+                self.row_groups.len(),
+                row_group.num_rows,
+                row_group.total_byte_size
+            ));
+            if !row_group.sorting_columns.is_empty() {
+                output.push_str(&format!(
+                    "Sorted by: {}\n\n",
+                    row_group.sorting_columns.join(", ")
+                ));
+            }
+
+            let mut table = Table::new();
+            table.load_preset("||--+-++|    ++++++");
+            table.set_header(
+                [
+                    "Column Name",
+                    "Logical Type",
+                    "Physical Type",
+                    "Distinct Values",
+                    "Nulls",
+                    "Min",
+                    "Max",
+                    "Encodings",
+                    "Codec",
+                    "Values",
+                    "Compressed",
+                    "Uncompressed",
+                    "Ratio",
+                    "Dictionary Offset",
+                    "Data Offset",
+                ]
+                .iter()
+                .map(Cell::new),
+            );
+            for column in &row_group.columns {
+                table.add_row(vec![
+                    column.name.clone(),
+                    or_not_available(&column.logical_type),
+                    column.physical_type.clone(),
+                    or_not_available(&column.distinct_count),
+                    or_not_available(&column.null_count),
+                    or_not_available(&column.min),
+                    or_not_available(&column.max),
+                    column.encodings.join(", "),
+                    column.compression.clone(),
+                    column.num_values.to_string(),
+                    column.compressed_size.to_string(),
+                    column.uncompressed_size.to_string(),
+                    format_ratio(column.compression_ratio()),
+                    or_not_available(&column.dictionary_page_offset),
+                    column.data_page_offset.to_string(),
+                ]);
+            }
+            output.push_str(&table.to_string());
+        }
+
+        output.push_str("\n\nColumn totals across all row groups:\n\n");
+        let mut table = Table::new();
+        table.load_preset("||--+-++|    ++++++");
+        table.set_header(
+            [
+                "Column Name",
+                "Encodings",
+                "Codec",
+                "Values",
+                "Nulls",
+                "Compressed",
+                "Uncompressed",
+                "Ratio",
+            ]
+            .iter()
+            .map(Cell::new),
+        );
+        for total in self.column_totals() {
+            table.add_row(vec![
+                total.name.clone(),
+                total.encodings.join(", "),
+                total.compression.join(", "),
+                total.num_values.to_string(),
+                or_not_available(&total.null_count),
+                total.compressed_size.to_string(),
+                total.uncompressed_size.to_string(),
+                format_ratio(total.compression_ratio()),
+            ]);
+        }
+        output.push_str(&table.to_string());
+        output
+    }
+
+    /// Render the report as a JSON document with the row groups and their columns nested
+    /// inside the file metadata
+    pub fn to_json(&self) -> serde_json::Value {
+        let key_value_metadata: serde_json::Map<String, serde_json::Value> = self
+            .key_value_metadata
+            .iter()
+            .map(|(key, value)| (key.clone(), json!(value)))
+            .collect();
+        let row_groups: Vec<serde_json::Value> = self
+            .row_groups
+            .iter()
+            .enumerate()
+            .map(|(i, row_group)| {
+                let columns: Vec<serde_json::Value> = row_group
+                    .columns
+                    .iter()
+                    .map(|column| {
+                        json!({
+                            "name": column.name,
+                            "logical_type": column.logical_type,
+                            "physical_type": column.physical_type,
+                            "distinct_count": column.distinct_count,
+                            "null_count": column.null_count,
+                            "min": column.min,
+                            "max": column.max,
+                            "encodings": column.encodings,
+                            "compression": column.compression,
+                            "num_values": column.num_values,
+                            "compressed_size": column.compressed_size,
+                            "uncompressed_size": column.uncompressed_size,
+                            "compression_ratio": column.compression_ratio(),
+                            "dictionary_page_offset": column.dictionary_page_offset,
+                            "data_page_offset": column.data_page_offset,
+                        })
+                    })
+                    .collect();
+                json!({
+                    "index": i,
+                    "num_rows": row_group.num_rows,
+                    "total_byte_size": row_group.total_byte_size,
+                    "sorting_columns": row_group.sorting_columns,
+                    "columns": columns,
+                })
+            })
+            .collect();
+        let column_totals: Vec<serde_json::Value> = self
+            .column_totals()
+            .iter()
+            .map(|total| {
+                json!({
+                    "name": total.name,
+                    "encodings": total.encodings,
+                    "compression": total.compression,
+                    "num_values": total.num_values,
+                    "null_count": total.null_count,
+                    "compressed_size": total.compressed_size,
+                    "uncompressed_size": total.uncompressed_size,
+                    "compression_ratio": total.compression_ratio(),
+                })
+            })
+            .collect();
+        json!({
+            "version": self.version,
+            "created_by": self.created_by,
+            "num_rows": self.num_rows,
+            "num_row_groups": self.row_groups.len(),
+            "key_value_metadata": key_value_metadata,
+            "row_groups": row_groups,
+            "column_totals": column_totals,
+        })
+    }
+
+    /// Render the report as CSV with one row per column chunk, so that the sizes of row groups
+    /// can be loaded as a table
+    pub fn to_csv(&self) -> Result<String, Error> {
+        let mut writer = ::csv::Writer::from_writer(vec![]);
+        writer
+            .write_record([
+                "row_group",
+                "row_group_num_rows",
+                "row_group_total_byte_size",
+                "column",
+                "logical_type",
+                "physical_type",
+                "distinct_count",
+                "null_count",
+                "min",
+                "max",
+                "encodings",
+                "compression",
+                "num_values",
+                "compressed_size",
+                "uncompressed_size",
+                "compression_ratio",
+                "dictionary_page_offset",
+                "data_page_offset",
+            ])
+            .map_err(csv_error)?;
+        for (i, row_group) in self.row_groups.iter().enumerate() {
+            for column in &row_group.columns {
+                writer
+                    .write_record([
+                        i.to_string(),
+                        row_group.num_rows.to_string(),
+                        row_group.total_byte_size.to_string(),
+                        column.name.clone(),
+                        or_empty(&column.logical_type),
+                        column.physical_type.clone(),
+                        or_empty(&column.distinct_count),
+                        or_empty(&column.null_count),
+                        or_empty(&column.min),
+                        or_empty(&column.max),
+                        column.encodings.join(" "),
+                        column.compression.clone(),
+                        column.num_values.to_string(),
+                        column.compressed_size.to_string(),
+                        column.uncompressed_size.to_string(),
+                        or_empty(&column.compression_ratio()),
+                        or_empty(&column.dictionary_page_offset),
+                        column.data_page_offset.to_string(),
+                    ])
+                    .map_err(csv_error)?;
+            }
+        }
+        let bytes = writer
+            .into_inner()
+            .map_err(|e| Error::IoError(e.into_error()))?;
+        String::from_utf8(bytes).map_err(|e| Error::General(e.to_string()))
+    }
+
+    /// Render the report in the given format
+    pub fn render(&self, format: ReportFormat) -> Result<String, Error> {
+        match format {
+            ReportFormat::Table => Ok(self.to_table_string()),
+            ReportFormat::Json => Ok(serde_json::to_string_pretty(&self.to_json())?),
+            ReportFormat::Csv => self.to_csv(),
+        }
+    }
+}
+
+fn or_not_available<T: ToString>(value: &Option<T>) -> String {
+    value
+        .as_ref()
+        .map_or_else(|| "N/A".to_string(), |v| v.to_string())
+}
+
+fn format_ratio(ratio: Option<f64>) -> String {
+    ratio.map_or_else(|| "N/A".to_string(), |ratio| format!("{:.2}", ratio))
+}
+
+fn or_empty<T: ToString>(value: &Option<T>) -> String {
+    value.as_ref().map_or_else(String::new, |v| v.to_string())
+}
+
+/// Print the footer metadata of a Parquet file in the given format
+pub fn view_parquet_meta(path: PathBuf, format: ReportFormat) -> Result<(), Error> {
+    let report = parquet_metadata_report(&path)?;
+    let output = report.render(format)?;
+    match format {
+        // the CSV output already ends with a newline
+        ReportFormat::Csv => print!("{}", output),
+        _ => println!("{}", output),
+    }
+    Ok(())
+}
+
+/// Read the key-value metadata from the footer of a Parquet file, except for the serialized
+/// Arrow schema
+pub fn read_key_value_metadata(path: &Path) -> Result<Vec<(String, String)>, Error> {
+    let reader = SerializedFileReader::new(File::open(path)?)?;
+    Ok(reader
+        .metadata()
+        .file_metadata()
+        .key_value_metadata()
+        .map(|kv| {
+            kv.iter()
+                .filter(|kv| kv.key != ARROW_SCHEMA_META_KEY)
+                .map(|kv| (kv.key.clone(), kv.value.clone().unwrap_or_default()))
+                .collect()
+        })
+        .unwrap_or_default())
+}
+
+/// Check which row groups might contain a value, using the bloom filters for a column
+pub fn probe_bloom_filter(path: PathBuf, column: &str, value: &str) -> Result<(), Error> {
     let file = File::open(path).map_err(Error::from)?;
-    let reader = SerializedFileReader::new(file).map_err(Error::from)?;
+    let options = ReadOptionsBuilder::new()
+        .with_reader_properties(
+            ReaderProperties::builder()
+                .set_read_bloom_filter(true)
+                .build(),
+        )
+        .build();
+    let reader = SerializedFileReader::new_with_options(file, options).map_err(Error::from)?;
 
     let parquet_metadata = reader.metadata();
+    let schema = parquet_metadata.file_metadata().schema_descr();
+    let column_index = (0..schema.num_columns())
+        .find(|i| schema.column(*i).path().string() == column)
+        .ok_or_else(|| Error::General(format!("Column '{}' not found", column)))?;
+    let physical_type = schema.column(column_index).physical_type();
 
     let mut table = Table::new();
     table.load_preset("||--+-++|    ++++++");
-    table.set_header(vec![Cell::new("Key"), Cell::new("Value")]);
-    let file_meta = parquet_metadata.file_metadata();
-    table.add_row(vec![
-        Cell::new("Version"),
-        Cell::new(format!("{}", file_meta.version())),
-    ]);
-    table.add_row(vec![
-        Cell::new("Created By"),
-        Cell::new(file_meta.created_by().unwrap_or("N/A")),
-    ]);
-    table.add_row(vec![
+    table.set_header(vec![
+        Cell::new("Row Group"),
         Cell::new("Rows"),
-        Cell::new(format!("{}", file_meta.num_rows())),
+        Cell::new("Result"),
     ]);
-    table.add_row(vec![
-        Cell::new("Row Groups"),
-        Cell::new(format!("{}", parquet_metadata.num_row_groups())),
-    ]);
-    println!("{}", table);
-
+    let mut matches = 0;
     for i in 0..parquet_metadata.num_row_groups() {
         let row_group_reader = reader.get_row_group(i)?;
-        let md = row_group_reader.metadata();
-        println!(
-            "\nRow Group {} of {} contains {} rows and has {} bytes:\n",
-            i,
-            parquet_metadata.num_row_groups(),
-            md.num_rows(),
-            md.total_byte_size()
-        );
-
-        let mut table = Table::new();
-        table.load_preset("||--+-++|    ++++++");
-        let header: Vec<Cell> = [
-            "Column Name",
-            "Logical Type",
-            "Physical Type",
-            "Distinct Values",
-            "Nulls",
-            "Min",
-            "Max",
-        ]
-        .iter()
-        .map(Cell::new)
-        .collect();
-        table.set_header(header);
-
-        let not_available = "N/A".to_string();
-        for column in md.columns() {
-            let mut row: Vec<String> = vec![];
-            row.push(column.column_descr().name().to_owned());
-            if let Some(t) = column.column_descr().logical_type() {
-                row.push(format!("{:?}", t));
-            } else {
-                row.push(not_available.clone());
-            }
-            match column.statistics() {
-                Some(stats) => {
-                    row.push(format!("{}", stats.physical_type()));
-                    if let Some(dc) = stats.distinct_count() {
-                        row.push(format!("{}", dc));
-                    } else {
-                        row.push(not_available.clone());
+        let result = match row_group_reader.get_column_bloom_filter(column_index) {
+            Some(bloom_filter) => {
+                let might_contain = match physical_type {
+                    Type::BOOLEAN => bloom_filter.check(&parse_value::<bool>(value)?),
+                    Type::INT32 => bloom_filter.check(&parse_value::<i32>(value)?),
+                    Type::INT64 => bloom_filter.check(&parse_value::<i64>(value)?),
+                    Type::FLOAT => bloom_filter.check(&parse_value::<f32>(value)?),
+                    Type::DOUBLE => bloom_filter.check(&parse_value::<f64>(value)?),
+                    Type::BYTE_ARRAY => bloom_filter.check(&ByteArray::from(value)),
+                    Type::FIXED_LEN_BYTE_ARRAY => {
+                        bloom_filter.check(&FixedLenByteArray::from(ByteArray::from(value)))
                     }
-                    row.push(format!("{}", stats.null_count()));
-
-                    if stats.has_min_max_set() {
-                        match &stats {
-                            Statistics::Boolean(v) => {
-                                row.push(format!("{}", v.min()));
-                                row.push(format!("{}", v.max()));
-                            }
-                            Statistics::Int32(v) => {
-                                row.push(format!("{}", v.min()));
-                                row.push(format!("{}", v.max()));
-                            }
-                            Statistics::Int64(v) => {
-                                row.push(format!("{}", v.min()));
-                                row.push(format!("{}", v.max()));
-                            }
-                            Statistics::Float(v) => {
-                                row.push(format!("{}", v.min()));
-                                row.push(format!("{}", v.max()));
-                            }
-                            Statistics::Double(v) => {
-                                row.push(format!("{}", v.min()));
-                                row.push(format!("{}", v.max()));
-                            }
-                            Statistics::ByteArray(v) => {
-                                match column.column_descr().logical_type() {
-                                    Some(LogicalType::String) => {
-                                        let min = v.min().as_utf8().unwrap();
-                                        let max = v.max().as_utf8().unwrap();
-                                        row.push(min.to_string());
-                                        row.push(max.to_string());
-                                    }
-                                    _ => {
-                                        row.push(format!("{}", v.min()));
-                                        row.push(format!("{}", v.max()));
-                                    }
-                                }
-                            }
-                            _ => {
-                                row.push("unsupported".to_owned());
-                                row.push("unsupported".to_owned());
-                            }
-                        }
-                    } else {
-                        row.push(not_available.clone());
-                        row.push(not_available.clone());
-                    }
-                }
-                _ => {
-                    for _ in 0..5 {
-                        row.push(not_available.clone());
+                    Type::INT96 => {
+                        return Err(Error::General(
+                            "Bloom filters for INT96 columns are not supported".to_string(),
+                        ))
                     }
+                };
+                if might_contain {
+                    matches += 1;
+                    "might contain value"
+                } else {
+                    "does not contain value"
                 }
             }
-            table.add_row(row);
+            None => {
+                matches += 1;
+                "no bloom filter"
+            }
+        };
+        table.add_row(vec![
+            Cell::new(format!("{}", i)),
+            Cell::new(format!("{}", row_group_reader.metadata().num_rows())),
+            Cell::new(result),
+        ]);
+    }
+    println!("{}", table);
+    println!(
+        "{} of {} row groups might contain '{}' in column '{}'",
+        matches,
+        parquet_metadata.num_row_groups(),
+        value,
+        column
+    );
+    Ok(())
+}
+
+/// One page of a column chunk, as shown by `view-parquet-pages`
+#[derive(Debug, Clone, PartialEq)]
+pub struct PageReport {
+    pub row_group: usize,
+    /// Position of the page within the column chunk
+    pub page: usize,
+    /// Byte offset of the page header in the file
+    pub offset: u64,
+    pub page_type: String,
+    pub encoding: Option<String>,
+    pub num_values: Option<i32>,
+    pub compressed_size: i32,
+    pub uncompressed_size: i32,
+    /// Minimum value from the column index, for data pages of files with a page index
+    pub min: Option<String>,
+    /// Maximum value from the column index, for data pages of files with a page index
+    pub max: Option<String>,
+}
+
+/// Read the page headers of a column, in all row groups or only in the given row group. The
+/// minimum and maximum values of data pages are read from the column index when the file has
+/// one.
+pub fn parquet_page_report(
+    path: &Path,
+    column: &str,
+    row_group: Option<usize>,
+) -> Result<Vec<PageReport>, Error> {
+    let options = ReadOptionsBuilder::new().with_page_index().build();
+    let reader = SerializedFileReader::new_with_options(File::open(path)?, options)?;
+    let parquet_metadata = reader.metadata();
+    let schema = parquet_metadata.file_metadata().schema_descr();
+    let column_index = (0..schema.num_columns())
+        .find(|i| schema.column(*i).path().string() == column)
+        .ok_or_else(|| Error::General(format!("Column '{}' not found", column)))?;
+    let logical_type = schema.column(column_index).logical_type();
+    let row_groups = match row_group {
+        Some(i) if i >= parquet_metadata.num_row_groups() => {
+            return Err(Error::General(format!(
+                "Row group {} not found, the file has {} row groups",
+                i,
+                parquet_metadata.num_row_groups()
+            )))
+        }
+        Some(i) => i..i + 1,
+        None => 0..parquet_metadata.num_row_groups(),
+    };
+
+    // only the page headers are read, seeking past the page data in between
+    let mut file = BufReader::new(File::open(path)?);
+    let mut pages = vec![];
+    for i in row_groups {
+        let chunk = parquet_metadata.row_group(i).column(column_index);
+        let (start, length) = chunk.byte_range();
+        file.seek(SeekFrom::Start(start))?;
+        // the column index has an entry for each data page, but not for dictionary pages
+        let min_max = parquet_metadata
+            .column_index()
+            .map(|index| index_min_max(&index[i][column_index], logical_type.as_ref()))
+            .unwrap_or_default();
+        let mut min_max = min_max.into_iter();
+
+        let (mut position, mut page) = (start, 0);
+        while position < start + length {
+            let header = {
+                let mut protocol = TCompactInputProtocol::new(&mut file);
+                PageHeader::read_from_in_protocol(&mut protocol)
+                    .map_err(|e| Error::General(format!("Invalid page header: {}", e)))?
+            };
+            if header.compressed_page_size < 0 {
+                return Err(Error::General(format!(
+                    "Invalid page header: negative page size {} at offset {}",
+                    header.compressed_page_size, position
+                )));
+            }
+            let page_type = PageType::try_from(header.type_)?;
+            let (encoding, num_values) = if let Some(h) = &header.dictionary_page_header {
+                (Some(h.encoding), Some(h.num_values))
+            } else if let Some(h) = &header.data_page_header {
+                (Some(h.encoding), Some(h.num_values))
+            } else if let Some(h) = &header.data_page_header_v2 {
+                (Some(h.encoding), Some(h.num_values))
+            } else {
+                (None, None)
+            };
+            let (min, max) = match page_type {
+                PageType::DATA_PAGE | PageType::DATA_PAGE_V2 => min_max.next().unwrap_or_default(),
+                _ => (None, None),
+            };
+            pages.push(PageReport {
+                row_group: i,
+                page,
+                offset: position,
+                page_type: page_type_name(page_type).to_string(),
+                encoding: encoding
+                    .map(Encoding::try_from)
+                    .transpose()?
+                    .map(|e| e.to_string()),
+                num_values,
+                compressed_size: header.compressed_page_size,
+                uncompressed_size: header.uncompressed_page_size,
+                min,
+                max,
+            });
+            file.seek_relative(header.compressed_page_size as i64)?;
+            position = file.stream_position()?;
+            page += 1;
         }
+    }
+    Ok(pages)
+}
 
-        println!("{}", table);
+fn page_type_name(page_type: PageType) -> &'static str {
+    match page_type {
+        PageType::DICTIONARY_PAGE => "Dictionary",
+        PageType::DATA_PAGE => "Data (v1)",
+        PageType::DATA_PAGE_V2 => "Data (v2)",
+        PageType::INDEX_PAGE => "Index",
     }
+}
+
+/// The minimum and maximum value of each data page in a column index
+fn index_min_max(
+    index: &Index,
+    logical_type: Option<&LogicalType>,
+) -> Vec<(Option<String>, Option<String>)> {
+    fn min_max<T>(
+        pages: &[PageIndex<T>],
+        format: impl Fn(&T) -> String,
+    ) -> Vec<(Option<String>, Option<String>)> {
+        pages
+            .iter()
+            .map(|page| (page.min().map(&format), page.max().map(&format)))
+            .collect()
+    }
+    match index {
+        Index::NONE => vec![],
+        Index::BOOLEAN(index) => min_max(&index.indexes, |v| v.to_string()),
+        Index::INT32(index) => min_max(&index.indexes, |v| v.to_string()),
+        Index::INT64(index) => min_max(&index.indexes, |v| v.to_string()),
+        Index::INT96(index) => min_max(&index.indexes, |v| v.to_string()),
+        Index::FLOAT(index) => min_max(&index.indexes, |v| v.to_string()),
+        Index::DOUBLE(index) => min_max(&index.indexes, |v| v.to_string()),
+        Index::BYTE_ARRAY(index) => match logical_type {
+            Some(LogicalType::String) => min_max(&index.indexes, |v| match v.as_utf8() {
+                Ok(s) => s.to_string(),
+                Err(_) => v.to_string(),
+            }),
+            _ => min_max(&index.indexes, |v| v.to_string()),
+        },
+        Index::FIXED_LEN_BYTE_ARRAY(index) => min_max(&index.indexes, |v| v.to_string()),
+    }
+}
+
+/// Print the pages of a column in a Parquet file
+pub fn view_parquet_pages(
+    path: PathBuf,
+    column: &str,
+    row_group: Option<usize>,
+) -> Result<(), Error> {
+    let pages = parquet_page_report(&path, column, row_group)?;
+    let mut table = Table::new();
+    table.load_preset("||--+-++|    ++++++");
+    table.set_header(
+        [
+            "Row Group",
+            "Page",
+            "Offset",
+            "Page Type",
+            "Encoding",
+            "Values",
+            "Compressed",
+            "Uncompressed",
+            "Min",
+            "Max",
+        ]
+        .iter()
+        .map(Cell::new),
+    );
+    for page in &pages {
+        table.add_row(vec![
+            page.row_group.to_string(),
+            page.page.to_string(),
+            page.offset.to_string(),
+            page.page_type.clone(),
+            or_not_available(&page.encoding),
+            or_not_available(&page.num_values),
+            page.compressed_size.to_string(),
+            page.uncompressed_size.to_string(),
+            or_not_available(&page.min),
+            or_not_available(&page.max),
+        ]);
+    }
+    println!("{}", table);
+    let count = |page_type: &str| {
+        pages
+            .iter()
+            .filter(|p| p.page_type.starts_with(page_type))
+            .count()
+    };
+    println!(
+        "Column '{}' has {} pages: {} dictionary, {} data",
+        column,
+        pages.len(),
+        count("Dictionary"),
+        count("Data")
+    );
     Ok(())
 }
+
+fn parse_value<T: FromStr>(value: &str) -> Result<T, Error> {
+    value.parse::<T>().map_err(|_| {
+        Error::General(format!(
+            "Cannot parse '{}' as {}",
+            value,
+            std::any::type_name::<T>()
+        ))
+    })
+}
@@ -0,0 +1,151 @@
+use datafusion::arrow::array::Array;
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::arrow::util::display::array_value_to_string;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// Directory name used for null partition values, as in Hive
+const NULL_PARTITION: &str = "__HIVE_DEFAULT_PARTITION__";
+
+/// Options for writing Hive-style partitioned output
+#[derive(Debug, Clone, Default)]
+pub struct PartitionOptions {
+    /// Columns to partition by, in directory order
+    pub columns: Vec<String>,
+    /// Leave the partition columns out of the data files, since their values are encoded in the
+    /// directory names
+    pub exclude_columns: bool,
+    /// Maximum number of files to keep open at once. When the limit is reached, the least
+    /// recently used file is closed and later rows for that partition go to a new file.
+    pub max_open_files: Option<usize>,
+}
+
+/// Group the rows of a batch by partition directory, such as `region=EU/date=2024-01-01`,
+/// preserving the order in which partitions first appear
+pub(crate) fn group_rows(
+    batch: &RecordBatch,
+    columns: &[String],
+    partition_indices: &[usize],
+) -> Vec<(PathBuf, Vec<u32>)> {
+    if columns.is_empty() {
+        return vec![(PathBuf::new(), (0..batch.num_rows() as u32).collect())];
+    }
+    let mut groups: Vec<(PathBuf, Vec<u32>)> = vec![];
+    let mut lookup: HashMap<PathBuf, usize> = HashMap::new();
+    for row in 0..batch.num_rows() {
+        let dir: PathBuf = columns
+            .iter()
+            .zip(partition_indices)
+            .map(|(name, i)| {
+                format!(
+                    "{}={}",
+                    escape_path_name(name),
+                    partition_value(batch.column(*i), row)
+                )
+            })
+            .collect();
+        let group = *lookup.entry(dir.clone()).or_insert_with(|| {
+            groups.push((dir, vec![]));
+            groups.len() - 1
+        });
+        groups[group].1.push(row as u32);
+    }
+    groups
+}
+
+fn partition_value(array: &dyn Array, row: usize) -> String {
+    if array.is_null(row) {
+        return NULL_PARTITION.to_string();
+    }
+    match array_value_to_string(array, row) {
+        Ok(value) if !value.is_empty() => escape_path_name(&value),
+        _ => NULL_PARTITION.to_string(),
+    }
+}
+
+/// Percent-encode characters that are not allowed in partition directory names, using the same
+/// set of characters as Hive
+fn escape_path_name(name: &str) -> String {
+    let mut escaped = String::new();
+    for ch in name.chars() {
+        if ch.is_control() || "\"#%'*/:=?\\{[]^".contains(ch) {
+            let mut buf = [0; 4];
+            for byte in ch.encode_utf8(&mut buf).bytes() {
+                escaped.push_str(&format!("%{:02X}", byte));
+            }
+        } else {
+            escaped.push(ch);
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::arrow::array::{Int32Array, StringArray};
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+    use std::sync::Arc;
+
+    #[test]
+    fn escape_special_characters() {
+        assert_eq!(escape_path_name("plain value-1_2.3"), "plain value-1_2.3");
+        assert_eq!(escape_path_name("a/b=c"), "a%2Fb%3Dc");
+        assert_eq!(escape_path_name("50%?"), "50%25%3F");
+        assert_eq!(escape_path_name("x[0]^{y}"), "x%5B0%5D%5E%7By}");
+        assert_eq!(escape_path_name("tab\there"), "tab%09here");
+        assert_eq!(escape_path_name("\u{85}é"), "%C2%85é");
+    }
+
+    #[test]
+    fn group_rows_by_escaped_values() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("region", DataType::Utf8, true),
+            Field::new("a/b", DataType::Int32, true),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(Int32Array::from(vec![1, 2, 3, 4, 5])),
+                Arc::new(StringArray::from(vec![
+                    Some("US/East"),
+                    None,
+                    Some("US/East"),
+                    Some(""),
+                    Some("EU"),
+                ])),
+                Arc::new(Int32Array::from(vec![
+                    Some(1),
+                    None,
+                    Some(1),
+                    None,
+                    Some(2),
+                ])),
+            ],
+        )
+        .unwrap();
+        let columns = vec!["region".to_string(), "a/b".to_string()];
+        let groups = group_rows(&batch, &columns, &[1, 2]);
+        let expected = vec![
+            (PathBuf::from("region=US%2FEast/a%2Fb=1"), vec![0, 2]),
+            (
+                PathBuf::from("region=__HIVE_DEFAULT_PARTITION__/a%2Fb=__HIVE_DEFAULT_PARTITION__"),
+                vec![1, 3],
+            ),
+            (PathBuf::from("region=EU/a%2Fb=2"), vec![4]),
+        ];
+        assert_eq!(groups, expected);
+    }
+
+    #[test]
+    fn group_rows_without_partition_columns() {
+        let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Int32, false)]));
+        let batch =
+            RecordBatch::try_new(schema, vec![Arc::new(Int32Array::from(vec![1, 2, 3]))]).unwrap();
+        assert_eq!(
+            group_rows(&batch, &[], &[]),
+            vec![(PathBuf::new(), vec![0, 1, 2])]
+        );
+    }
+}
@@ -0,0 +1,213 @@
+use crate::utils::file_format;
+use crate::{Error, FileFormat};
+use datafusion::arrow::datatypes::SchemaRef;
+use datafusion::datasource::streaming::StreamingTable;
+use datafusion::execution::TaskContext;
+use datafusion::parquet::file::reader::{FileReader, SerializedFileReader};
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+use datafusion::physical_plan::streaming::PartitionStream;
+use datafusion::physical_plan::{ExecutionPlan, SendableRecordBatchStream};
+use datafusion::prelude::{DataFrame, SessionContext};
+use futures::TryStreamExt;
+use indicatif::{HumanBytes, HumanCount, ProgressBar, ProgressState, ProgressStyle};
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Reports the number of rows processed by a command with a progress bar on stderr, and prints
+/// a summary of the rows, bytes and throughput to stderr when the command finishes.
+///
+/// The progress bar is only drawn when stderr is a terminal. Nothing is reported when the
+/// progress is created with `quiet` set.
+pub struct Progress {
+    bar: ProgressBar,
+    quiet: bool,
+    start: Instant,
+    input_bytes: u64,
+}
+
+impl Progress {
+    /// Start reporting progress for a command that reads the given input files or directories.
+    /// When `count_rows` is set and every input is a Parquet file, the total number of rows is
+    /// read from the file footers so that the progress bar can show an ETA.
+    pub fn start(inputs: &[&str], count_rows: bool, quiet: bool) -> Self {
+        let input_bytes = inputs.iter().map(|input| path_size(Path::new(input))).sum();
+        let total_rows = if count_rows {
+            inputs
+                .iter()
+                .map(|input| parquet_row_count(Path::new(input)))
+                .sum::<Option<u64>>()
+        } else {
+            None
+        };
+        let bar = if quiet {
+            ProgressBar::hidden()
+        } else if let Some(total_rows) = total_rows {
+            ProgressBar::new(total_rows).with_style(style(
+                "[{elapsed_precise}] {bar:40} {human_pos}/{human_len} rows ({rate}, ETA {eta})",
+            ))
+        } else {
+            ProgressBar::new_spinner().with_style(style(
+                "{spinner} [{elapsed_precise}] {human_pos} rows ({rate})",
+            ))
+        };
+        if !quiet {
+            bar.enable_steady_tick(Duration::from_millis(200));
+        }
+        Self {
+            bar,
+            quiet,
+            start: Instant::now(),
+            input_bytes,
+        }
+    }
+
+    /// Count the rows produced by a DataFrame as it is executed
+    pub async fn track(&self, df: DataFrame) -> Result<DataFrame, Error> {
+        if self.quiet {
+            return Ok(df);
+        }
+        let (state, plan) = df.into_parts();
+        let plan = state.create_physical_plan(&plan).await?;
+        let partitions = (0..plan.output_partitioning().partition_count())
+            .map(|partition| {
+                Arc::new(TrackedPartition {
+                    schema: plan.schema(),
+                    plan: plan.clone(),
+                    partition,
+                    bar: self.bar.clone(),
+                }) as Arc<dyn PartitionStream>
+            })
+            .collect();
+        let table = StreamingTable::try_new(plan.schema(), partitions)?;
+        Ok(SessionContext::new_with_state(state).read_table(Arc::new(table))?)
+    }
+
+    /// The number of rows processed so far
+    pub fn rows(&self) -> u64 {
+        self.bar.position()
+    }
+
+    /// Remove the progress bar and print a summary on stderr. When `output` is given, the summary
+    /// includes the size of the output and how it compares to the size of the input.
+    pub fn finish(&self, output: Option<&str>) {
+        self.bar.finish_and_clear();
+        if self.quiet {
+            return;
+        }
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let rows = self.rows();
+        let (rows_per_second, bytes_per_second) = if elapsed > 0.0 {
+            (
+                (rows as f64 / elapsed) as u64,
+                (self.input_bytes as f64 / elapsed) as u64,
+            )
+        } else {
+            (rows, self.input_bytes)
+        };
+        let throughput = format!(
+            "{} rows/s, {}/s",
+            HumanCount(rows_per_second),
+            HumanBytes(bytes_per_second)
+        );
+        match output {
+            Some(output) => {
+                let output_bytes = path_size(Path::new(output));
+                // the formats of the input and output usually differ, so this is only a comparison
+                // of the file sizes rather than a compression ratio
+                let ratio = if self.input_bytes > 0 {
+                    format!(
+                        ", {:.2}x the input size",
+                        output_bytes as f64 / self.input_bytes as f64
+                    )
+                } else {
+                    String::new()
+                };
+                eprintln!(
+                    "Wrote {} rows to {} in {:.3}s ({}): {} read, {} written{}",
+                    HumanCount(rows),
+                    output,
+                    elapsed,
+                    throughput,
+                    HumanBytes(self.input_bytes),
+                    HumanBytes(output_bytes),
+                    ratio
+                );
+            }
+            None => eprintln!(
+                "Read {} rows in {:.3}s ({}): {} read",
+                HumanCount(rows),
+                elapsed,
+                throughput,
+                HumanBytes(self.input_bytes)
+            ),
+        }
+    }
+}
+
+fn style(template: &str) -> ProgressStyle {
+    ProgressStyle::with_template(template).unwrap().with_key(
+        "rate",
+        |state: &ProgressState, w: &mut dyn std::fmt::Write| {
+            let _ = write!(w, "{} rows/s", HumanCount(state.per_sec() as u64));
+        },
+    )
+}
+
+/// One partition of an execution plan that advances a progress bar by the rows it produces
+struct TrackedPartition {
+    schema: SchemaRef,
+    plan: Arc<dyn ExecutionPlan>,
+    partition: usize,
+    bar: ProgressBar,
+}
+
+impl PartitionStream for TrackedPartition {
+    fn schema(&self) -> &SchemaRef {
+        &self.schema
+    }
+
+    fn execute(&self, ctx: Arc<TaskContext>) -> SendableRecordBatchStream {
+        let schema = self.schema.clone();
+        let bar = self.bar.clone();
+        let stream = futures::stream::once(futures::future::ready(
+            self.plan.execute(self.partition, ctx),
+        ))
+        .try_flatten()
+        .inspect_ok(move |batch| bar.inc(batch.num_rows() as u64));
+        Box::pin(RecordBatchStreamAdapter::new(schema, stream))
+    }
+}
+
+/// The total size of a file, or of the files in a directory and its subdirectories
+fn path_size(path: &Path) -> u64 {
+    if path.is_dir() {
+        std::fs::read_dir(path)
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok())
+                    .map(|entry| path_size(&entry.path()))
+                    .sum()
+            })
+            .unwrap_or(0)
+    } else {
+        path.metadata().map(|m| m.len()).unwrap_or(0)
+    }
+}
+
+/// The number of rows in a Parquet file, or in the Parquet files of a directory, according to
+/// their footers. Returns `None` for other formats.
+fn parquet_row_count(path: &Path) -> Option<u64> {
+    if path.is_dir() {
+        return std::fs::read_dir(path)
+            .ok()?
+            .map(|entry| parquet_row_count(&entry.ok()?.path()))
+            .sum();
+    }
+    if !matches!(file_format(path.to_str()?), Ok(FileFormat::Parquet)) {
+        return None;
+    }
+    let reader = SerializedFileReader::new(File::open(path).ok()?).ok()?;
+    Some(reader.metadata().file_metadata().num_rows() as u64)
+}
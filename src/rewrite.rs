@@ -0,0 +1,224 @@
+use crate::convert::{write_output_as, ConvertOptions};
+use crate::progress::Progress;
+use crate::utils::{file_format, list_dir_files};
+use crate::{Error, FileFormat};
+use datafusion::arrow::datatypes::{Field, Schema};
+use datafusion::parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use datafusion::parquet::file::reader::{FileReader, SerializedFileReader};
+use datafusion::prelude::{ParquetReadOptions, SessionContext};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+/// Rewrite Parquet files into a new set of files, such as compacting a directory of many small
+/// files into a few large ones, or changing the row group size, compression and encodings.
+///
+/// Inputs can be Parquet files or directories, which are searched for Parquet files
+/// recursively. All inputs must have the same column names and types. The key-value metadata of
+/// the inputs is copied to the output. When `num_files` is given, the rows are split evenly
+/// between that many files in the output directory. An output without an extension is written
+/// as a directory of Parquet files.
+pub async fn rewrite_files(
+    ctx: &SessionContext,
+    inputs: &[PathBuf],
+    output_filename: &str,
+    num_files: Option<usize>,
+    options: &ConvertOptions,
+) -> Result<(), Error> {
+    // an output without an extension, such as events/, is a directory of Parquet files
+    let is_dir = Path::new(output_filename).extension().is_none();
+    if !is_dir && !matches!(file_format(output_filename), Ok(FileFormat::Parquet)) {
+        return Err(Error::General(format!(
+            "Cannot rewrite to '{}', the output must be a Parquet file or directory such as \
+             compacted.parquet or compacted/",
+            output_filename
+        )));
+    }
+    let mut files = vec![];
+    for input in inputs {
+        if input.is_dir() {
+            files.extend(
+                list_dir_files(input, None)?
+                    .into_iter()
+                    .filter(|path| is_parquet(path)),
+            );
+        } else if is_parquet(input) {
+            files.push(input.clone());
+        } else {
+            return Err(Error::General(format!(
+                "Cannot rewrite '{}', only Parquet files can be rewritten",
+                input.display()
+            )));
+        }
+    }
+    let filenames = files
+        .iter()
+        .map(|path| {
+            path.to_str()
+                .ok_or_else(|| Error::General("Invalid filename".to_string()))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if filenames.is_empty() {
+        return Err(Error::General("No Parquet files to rewrite".to_string()));
+    }
+    let schema = merge_schemas(&files)?;
+    println!(
+        "Rewriting {} Parquet file{} to {}",
+        filenames.len(),
+        if filenames.len() == 1 { "" } else { "s" },
+        output_filename
+    );
+
+    let mut options = options.clone();
+    options.parquet.keep_input_metadata = true;
+    options.parquet = options.parquet.with_input_metadata(&filenames)?;
+    let num_files = num_files.unwrap_or(1);
+    if num_files == 0 {
+        return Err(Error::General("File limits must be at least 1".to_string()));
+    }
+    // a directory output is written as numbered files, by default a single one
+    if num_files > 1 || (is_dir && !options.split.is_enabled()) {
+        let mut rows = 0;
+        for file in &files {
+            let reader = SerializedFileReader::new(File::open(file)?)?;
+            rows += reader.metadata().file_metadata().num_rows() as usize;
+        }
+        options.split.max_rows_per_file = Some(rows.div_ceil(num_files).max(1));
+    }
+    options.single_file = !options.split.is_enabled();
+
+    let progress = Progress::start(&filenames, true, options.quiet);
+    let read_options = ParquetReadOptions {
+        file_extension: "",
+        schema: Some(&schema),
+        ..Default::default()
+    };
+    let df = ctx.read_parquet(filenames.clone(), read_options).await?;
+    write_output_as(
+        df,
+        output_filename,
+        FileFormat::Parquet,
+        &progress,
+        &options,
+    )
+    .await?;
+    Ok(())
+}
+
+/// Fail unless all files have the same columns with the same types, ignoring schema and field
+/// metadata, and return the schema to read them with. A column is nullable if it is nullable in
+/// any of the files, so that files that only differ in nullability can be rewritten together.
+fn merge_schemas(files: &[PathBuf]) -> Result<Schema, Error> {
+    let mut merged: Option<(&PathBuf, Vec<Field>)> = None;
+    for file in files {
+        let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(file)?)?;
+        let fields = builder.schema().fields().clone();
+        match &mut merged {
+            None => {
+                let fields = fields.iter().map(|f| f.as_ref().clone()).collect();
+                merged = Some((file, fields));
+            }
+            Some((first, merged)) => {
+                let same_columns = merged.len() == fields.len()
+                    && merged
+                        .iter()
+                        .zip(fields.iter())
+                        .all(|(a, b)| a.name() == b.name() && a.data_type() == b.data_type());
+                if !same_columns {
+                    return Err(Error::General(format!(
+                        "Cannot rewrite files with different schemas: {} does not match {}",
+                        file.display(),
+                        first.display()
+                    )));
+                }
+                for (field, other) in merged.iter_mut().zip(fields.iter()) {
+                    if other.is_nullable() && !field.is_nullable() {
+                        *field = field.clone().with_nullable(true);
+                    }
+                }
+            }
+        }
+    }
+    let fields = merged.map(|(_, fields)| fields).unwrap_or_default();
+    Ok(Schema::new(fields))
+}
+
+fn is_parquet(path: &Path) -> bool {
+    matches!(
+        file_format(&path.to_string_lossy()),
+        Ok(FileFormat::Parquet)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::arrow::array::new_empty_array;
+    use datafusion::arrow::datatypes::DataType;
+    use datafusion::arrow::record_batch::RecordBatch;
+    use datafusion::parquet::arrow::ArrowWriter;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    /// Write an empty Parquet file with the given schema
+    fn write_parquet(dir: &TempDir, name: &str, schema: Schema) -> PathBuf {
+        let path = dir.path().join(name);
+        let schema = Arc::new(schema);
+        let columns = schema
+            .fields()
+            .iter()
+            .map(|f| new_empty_array(f.data_type()))
+            .collect();
+        let batch = RecordBatch::try_new(schema.clone(), columns).unwrap();
+        let mut writer = ArrowWriter::try_new(File::create(&path).unwrap(), schema, None).unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+        path
+    }
+
+    #[test]
+    fn merge_nullability_and_ignore_metadata() {
+        let dir = TempDir::new().unwrap();
+        let required = Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("name", DataType::Utf8, false),
+        ]);
+        let nullable = Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("name", DataType::Utf8, true)
+                .with_metadata(HashMap::from([("k".to_string(), "v".to_string())])),
+        ])
+        .with_metadata(HashMap::from([("source".to_string(), "b".to_string())]));
+        let files = [
+            write_parquet(&dir, "a.parquet", required),
+            write_parquet(&dir, "b.parquet", nullable),
+        ];
+        let merged = merge_schemas(&files).unwrap();
+        assert!(!merged.field(0).is_nullable());
+        assert!(merged.field(1).is_nullable());
+        assert_eq!(merged.field(1).data_type(), &DataType::Utf8);
+    }
+
+    #[test]
+    fn reject_different_columns() {
+        let dir = TempDir::new().unwrap();
+        let base = write_parquet(
+            &dir,
+            "base.parquet",
+            Schema::new(vec![Field::new("id", DataType::Int64, true)]),
+        );
+        let others = [
+            Schema::new(vec![Field::new("id", DataType::Int32, true)]),
+            Schema::new(vec![Field::new("key", DataType::Int64, true)]),
+            Schema::new(vec![
+                Field::new("id", DataType::Int64, true),
+                Field::new("name", DataType::Utf8, true),
+            ]),
+        ];
+        for (i, schema) in others.into_iter().enumerate() {
+            let other = write_parquet(&dir, &format!("{}.parquet", i), schema);
+            let err = merge_schemas(&[base.clone(), other]).unwrap_err();
+            assert!(err.to_string().contains("different schemas"), "{}", err);
+        }
+    }
+}
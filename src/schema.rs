@@ -0,0 +1,105 @@
+use crate::Error;
+use datafusion::arrow::datatypes::{DataType, Schema};
+use datafusion::logical_expr::{DdlStatement, LogicalPlan};
+use datafusion::prelude::SessionContext;
+use serde_json::Value;
+use std::path::Path;
+
+/// Load an explicit schema, either from a JSON schema file or from a list of column definitions
+/// such as `id INT, name TEXT NOT NULL`.
+///
+/// A JSON schema file contains an array of fields, optionally wrapped in an object with a
+/// `fields` key, where each field has a `name`, a SQL `type`, and an optional `nullable` flag:
+///
+/// ```json
+/// {"fields": [{"name": "id", "type": "BIGINT", "nullable": false}, {"name": "name", "type": "TEXT"}]}
+/// ```
+pub async fn load_schema(ctx: &SessionContext, schema: &str) -> Result<Schema, Error> {
+    let path = Path::new(schema);
+    let columns = if path.extension().is_some_and(|ext| ext == "json") && path.is_file() {
+        let json: Value = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        json_schema_to_ddl(&json)?
+    } else {
+        schema.to_string()
+    };
+    parse_ddl(ctx, &columns).await
+}
+
+/// Parse a SQL type name such as `BIGINT`, `DECIMAL(18,4)` or `TIMESTAMP` to an Arrow type
+pub async fn parse_data_type(ctx: &SessionContext, data_type: &str) -> Result<DataType, Error> {
+    let schema = parse_ddl(ctx, &format!("__column__ {}", data_type)).await?;
+    match schema.fields().first() {
+        Some(field) if schema.fields().len() == 1 => Ok(field.data_type().clone()),
+        _ => Err(Error::General(format!("Invalid data type: {}", data_type))),
+    }
+}
+
+/// Check that a file contains exactly the columns declared in the schema
+pub fn check_schema(expected: &Schema, columns: &[String]) -> Result<(), Error> {
+    let missing: Vec<&str> = expected
+        .fields()
+        .iter()
+        .map(|f| f.name().as_str())
+        .filter(|name| !columns.iter().any(|c| c == name))
+        .collect();
+    let extra: Vec<&str> = columns
+        .iter()
+        .map(|c| c.as_str())
+        .filter(|name| expected.field_with_name(name).is_err())
+        .collect();
+    if missing.is_empty() && extra.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::General(format!(
+            "File does not match schema: missing columns [{}], extra columns [{}]",
+            missing.join(", "),
+            extra.join(", ")
+        )))
+    }
+}
+
+/// Plan a `CREATE TABLE` statement for the column definitions so that SQL type names are
+/// mapped to Arrow types the same way as in queries
+async fn parse_ddl(ctx: &SessionContext, columns: &str) -> Result<Schema, Error> {
+    let sql = format!("CREATE TABLE __schema__ ({})", columns);
+    match ctx.state().create_logical_plan(&sql).await? {
+        LogicalPlan::Ddl(DdlStatement::CreateMemoryTable(create)) => {
+            Ok(create.input.schema().as_ref().into())
+        }
+        _ => Err(Error::General(format!("Invalid schema: {}", columns))),
+    }
+}
+
+fn json_schema_to_ddl(json: &Value) -> Result<String, Error> {
+    let fields = match json {
+        Value::Object(map) => map.get("fields"),
+        value => Some(value),
+    }
+    .and_then(|fields| fields.as_array())
+    .ok_or_else(|| Error::General("Schema file must contain an array of fields".to_string()))?;
+    let columns = fields
+        .iter()
+        .map(|field| {
+            let name = field.get("name").and_then(|v| v.as_str());
+            let data_type = field.get("type").and_then(|v| v.as_str());
+            let nullable = field.get("nullable").and_then(|v| v.as_bool());
+            match (name, data_type) {
+                (Some(name), Some(data_type)) => Ok(format!(
+                    "\"{}\" {}{}",
+                    name.replace('"', "\"\""),
+                    data_type,
+                    if nullable == Some(false) {
+                        " NOT NULL"
+                    } else {
+                        ""
+                    }
+                )),
+                _ => Err(Error::General(format!(
+                    "Schema field must have a name and a type: {}",
+                    field
+                ))),
+            }
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    Ok(columns.join(", "))
+}
@@ -0,0 +1,588 @@
+use crate::utils::file_ending;
+use crate::Error;
+use comfy_table::{Cell, Table};
+use datafusion::arrow::datatypes::{DataType, TimeUnit};
+use datafusion::prelude::CsvReadOptions;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// Number of bytes read from the start of the file when sniffing
+const SAMPLE_SIZE: usize = 64 * 1024;
+
+/// Maximum number of records to inspect when sniffing
+const MAX_SAMPLE_RECORDS: usize = 100;
+
+/// Candidate delimiters, in order of preference when scores are tied
+const DELIMITERS: [char; 5] = [',', '\t', ';', '|', ':'];
+
+/// Candidate quote characters, in order of preference when scores are tied
+const QUOTES: [char; 2] = ['"', '\''];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CharacterEncoding {
+    Utf8,
+    Utf8Bom,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+}
+
+impl Display for CharacterEncoding {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Utf8 => write!(f, "UTF-8"),
+            Self::Utf8Bom => write!(f, "UTF-8 (with BOM)"),
+            Self::Utf16Le => write!(f, "UTF-16LE"),
+            Self::Utf16Be => write!(f, "UTF-16BE"),
+            Self::Latin1 => write!(f, "ISO-8859-1"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineTerminator {
+    Lf,
+    CrLf,
+    Cr,
+}
+
+impl Display for LineTerminator {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Lf => write!(f, "LF (\\n)"),
+            Self::CrLf => write!(f, "CRLF (\\r\\n)"),
+            Self::Cr => write!(f, "CR (\\r)"),
+        }
+    }
+}
+
+/// The dialect detected by sniffing the start of a CSV file
+#[derive(Debug, Clone)]
+pub struct CsvDialect {
+    pub delimiter: u8,
+    pub quote: u8,
+    pub has_header: bool,
+    pub line_terminator: LineTerminator,
+    pub encoding: CharacterEncoding,
+    /// Column names and likely data types, based on the sampled records
+    pub columns: Vec<(String, DataType)>,
+    file_extension: String,
+}
+
+impl CsvDialect {
+    /// Create the DataFusion read options for this dialect, failing if the file cannot be read
+    /// by the CSV reader without being transcoded first
+    pub fn read_options(&self) -> Result<CsvReadOptions<'_>, Error> {
+        match self.encoding {
+            CharacterEncoding::Utf8 | CharacterEncoding::Utf8Bom => {}
+            other => {
+                return Err(Error::General(format!(
+                    "CSV files encoded as {} must be converted to UTF-8 before they can be read",
+                    other
+                )))
+            }
+        }
+        if self.encoding == CharacterEncoding::Utf8Bom && !self.has_header {
+            // the byte order mark would be read as part of the first value
+            return Err(Error::General(
+                "CSV files without a header row cannot start with a byte order mark".to_string(),
+            ));
+        }
+        // the CSV parser accepts a lone CR as a line terminator, but DataFusion splits large
+        // files into ranges at LF characters to read them in parallel, which fails when there
+        // are none
+        if self.line_terminator == LineTerminator::Cr {
+            return Err(Error::General(
+                "CSV files with CR line terminators are not supported".to_string(),
+            ));
+        }
+        Ok(CsvReadOptions::new()
+            .has_header(self.has_header)
+            .delimiter(self.delimiter)
+            .quote(self.quote)
+            .file_extension(&self.file_extension))
+    }
+}
+
+/// Detect the dialect of a CSV file by sampling the beginning of the file
+pub fn sniff_file(path: &Path) -> Result<CsvDialect, Error> {
+    let mut file = File::open(path)?;
+    let mut sample = Vec::with_capacity(SAMPLE_SIZE);
+    file.by_ref()
+        .take(SAMPLE_SIZE as u64)
+        .read_to_end(&mut sample)?;
+    let truncated = file.read(&mut [0_u8; 1])? > 0;
+
+    let (encoding, mut text) = decode(&sample);
+    if truncated {
+        // the last line is most likely incomplete
+        if let Some(i) = text.rfind(['\n', '\r']) {
+            text.truncate(i + 1);
+        }
+    }
+    if text.trim().is_empty() {
+        return Err(Error::General(format!(
+            "Cannot detect CSV dialect of empty file {}",
+            path.display()
+        )));
+    }
+
+    let line_terminator = detect_line_terminator(&text);
+    let quote = detect_quote(&text);
+    let records: Vec<&str> = split_records(&text, quote)
+        .into_iter()
+        .filter(|r| !r.is_empty())
+        .take(MAX_SAMPLE_RECORDS)
+        .collect();
+    let delimiter = detect_delimiter(&records, quote);
+    let rows: Vec<Vec<String>> = records
+        .iter()
+        .map(|r| split_fields(r, delimiter, quote))
+        .collect();
+    let has_header = detect_header(&rows);
+    let columns = infer_columns(&rows, has_header);
+
+    Ok(CsvDialect {
+        delimiter: delimiter as u8,
+        quote: quote as u8,
+        has_header,
+        line_terminator,
+        encoding,
+        columns,
+        file_extension: path
+            .to_str()
+            .and_then(|p| file_ending(p).ok())
+            .map(|ext| format!(".{}", ext))
+            .unwrap_or_default(),
+    })
+}
+
+/// Print the detected dialect of a CSV file
+pub fn view_csv_dialect(path: &Path) -> Result<(), Error> {
+    let dialect = sniff_file(path)?;
+
+    let mut table = Table::new();
+    table.load_preset("||--+-++|    ++++++");
+    table.set_header(vec![Cell::new("Option"), Cell::new("Value")]);
+    table.add_row(vec![
+        Cell::new("Encoding"),
+        Cell::new(dialect.encoding.to_string()),
+    ]);
+    table.add_row(vec![
+        Cell::new("Line Terminator"),
+        Cell::new(dialect.line_terminator.to_string()),
+    ]);
+    table.add_row(vec![
+        Cell::new("Delimiter"),
+        Cell::new(escape_char(dialect.delimiter as char)),
+    ]);
+    table.add_row(vec![
+        Cell::new("Quote"),
+        Cell::new(escape_char(dialect.quote as char)),
+    ]);
+    table.add_row(vec![
+        Cell::new("Has Header"),
+        Cell::new(format!("{}", dialect.has_header)),
+    ]);
+    println!("{}", table);
+
+    let mut table = Table::new();
+    table.load_preset("||--+-++|    ++++++");
+    table.set_header(vec![Cell::new("Column Name"), Cell::new("Data Type")]);
+    for (name, data_type) in &dialect.columns {
+        table.add_row(vec![Cell::new(name), Cell::new(format!("{}", data_type))]);
+    }
+    println!("{}", table);
+
+    if let Err(e) = dialect.read_options() {
+        eprintln!("Warning: {}", e);
+    }
+    Ok(())
+}
+
+fn escape_char(ch: char) -> String {
+    match ch {
+        '\t' => "\\t".to_string(),
+        other => other.to_string(),
+    }
+}
+
+/// Detect the character encoding from the byte order mark, falling back to Latin-1 when the
+/// sample is not valid UTF-8
+fn decode(sample: &[u8]) -> (CharacterEncoding, String) {
+    if let Some(bytes) = sample.strip_prefix(&[0xEF, 0xBB, 0xBF]) {
+        return (
+            CharacterEncoding::Utf8Bom,
+            String::from_utf8_lossy(bytes).to_string(),
+        );
+    }
+    if let Some(bytes) = sample.strip_prefix(&[0xFF, 0xFE]) {
+        let units: Vec<u16> = bytes
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .collect();
+        return (CharacterEncoding::Utf16Le, String::from_utf16_lossy(&units));
+    }
+    if let Some(bytes) = sample.strip_prefix(&[0xFE, 0xFF]) {
+        let units: Vec<u16> = bytes
+            .chunks_exact(2)
+            .map(|c| u16::from_be_bytes([c[0], c[1]]))
+            .collect();
+        return (CharacterEncoding::Utf16Be, String::from_utf16_lossy(&units));
+    }
+    match std::str::from_utf8(sample) {
+        Ok(text) => (CharacterEncoding::Utf8, text.to_string()),
+        // a multi-byte character may have been cut off at the end of the sample
+        Err(e) if e.error_len().is_none() => (
+            CharacterEncoding::Utf8,
+            String::from_utf8_lossy(&sample[..e.valid_up_to()]).to_string(),
+        ),
+        Err(_) => (
+            CharacterEncoding::Latin1,
+            sample.iter().map(|b| *b as char).collect(),
+        ),
+    }
+}
+
+fn detect_line_terminator(text: &str) -> LineTerminator {
+    let crlf = text.matches("\r\n").count();
+    let cr = text.matches('\r').count() - crlf;
+    let lf = text.matches('\n').count() - crlf;
+    if crlf > 0 && crlf >= lf && crlf >= cr {
+        LineTerminator::CrLf
+    } else if cr > lf {
+        LineTerminator::Cr
+    } else {
+        LineTerminator::Lf
+    }
+}
+
+/// Choose the quote character that most often encloses a complete field
+fn detect_quote(text: &str) -> char {
+    let mut best = (QUOTES[0], 0);
+    for quote in QUOTES {
+        let mut count = 0;
+        for line in text.lines() {
+            let chars: Vec<char> = line.chars().collect();
+            let mut i = 0;
+            while i < chars.len() {
+                if chars[i] == quote && (i == 0 || DELIMITERS.contains(&chars[i - 1])) {
+                    if let Some(j) = (i + 1..chars.len()).find(|j| {
+                        chars[*j] == quote
+                            && (*j + 1 == chars.len() || DELIMITERS.contains(&chars[*j + 1]))
+                    }) {
+                        count += 1;
+                        i = j + 1;
+                        continue;
+                    }
+                }
+                i += 1;
+            }
+        }
+        if count > best.1 {
+            best = (quote, count);
+        }
+    }
+    best.0
+}
+
+/// Split text into records, allowing line terminators inside quoted fields
+fn split_records(text: &str, quote: char) -> Vec<&str> {
+    let mut records = vec![];
+    let mut in_quotes = false;
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, ch)) = chars.next() {
+        if ch == quote {
+            in_quotes = !in_quotes;
+        } else if !in_quotes && (ch == '\n' || ch == '\r') {
+            records.push(&text[start..i]);
+            if ch == '\r' && matches!(chars.peek(), Some((_, '\n'))) {
+                chars.next();
+            }
+            start = chars.peek().map(|(j, _)| *j).unwrap_or(text.len());
+        }
+    }
+    if start < text.len() {
+        records.push(&text[start..]);
+    }
+    records
+}
+
+/// Choose the delimiter that appears the same number of times in the most records
+fn detect_delimiter(records: &[&str], quote: char) -> char {
+    let mut best = (DELIMITERS[0], 0.0, 0);
+    for delimiter in DELIMITERS {
+        let counts: Vec<usize> = records
+            .iter()
+            .map(|r| count_unquoted(r, delimiter, quote))
+            .collect();
+        let mut frequencies: Vec<(usize, usize)> = vec![];
+        for count in &counts {
+            match frequencies.iter_mut().find(|(c, _)| c == count) {
+                Some((_, n)) => *n += 1,
+                None => frequencies.push((*count, 1)),
+            }
+        }
+        if let Some((mode, n)) = frequencies
+            .into_iter()
+            .max_by_key(|(count, n)| (*n, *count))
+        {
+            let consistency = n as f64 / counts.len() as f64;
+            if mode > 0 && (consistency > best.1 || (consistency == best.1 && mode > best.2)) {
+                best = (delimiter, consistency, mode);
+            }
+        }
+    }
+    best.0
+}
+
+fn count_unquoted(record: &str, delimiter: char, quote: char) -> usize {
+    let mut in_quotes = false;
+    let mut count = 0;
+    for ch in record.chars() {
+        if ch == quote {
+            in_quotes = !in_quotes;
+        } else if ch == delimiter && !in_quotes {
+            count += 1;
+        }
+    }
+    count
+}
+
+fn split_fields(record: &str, delimiter: char, quote: char) -> Vec<String> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = record.chars().peekable();
+    while let Some(ch) = chars.next() {
+        if ch == quote {
+            if in_quotes && chars.peek() == Some(&quote) {
+                field.push(quote);
+                chars.next();
+            } else {
+                in_quotes = !in_quotes;
+            }
+        } else if ch == delimiter && !in_quotes {
+            fields.push(std::mem::take(&mut field));
+        } else {
+            field.push(ch);
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/// Decide whether the first row is a header by checking whether its values look different from
+/// the values in the rest of each column
+fn detect_header(rows: &[Vec<String>]) -> bool {
+    let Some((first, rest)) = rows.split_first() else {
+        return false;
+    };
+    let rest: Vec<&Vec<String>> = rest.iter().filter(|r| r.len() == first.len()).collect();
+    if rest.is_empty() {
+        return first.iter().all(|v| infer_type(v) == Some(DataType::Utf8));
+    }
+
+    let mut votes = 0;
+    for (i, header) in first.iter().enumerate() {
+        let values: Vec<&str> = rest.iter().map(|r| r[i].as_str()).collect();
+        match infer_column_type(&values) {
+            DataType::Utf8 => {
+                // text columns with a fixed width, such as codes, only give a signal when the
+                // first value has a different width
+                let width = values[0].chars().count();
+                if values.iter().all(|v| v.chars().count() == width) {
+                    if header.chars().count() == width {
+                        votes -= 1;
+                    } else {
+                        votes += 1;
+                    }
+                }
+            }
+            data_type => {
+                if infer_type(header) == Some(data_type) {
+                    votes -= 1;
+                } else {
+                    votes += 1;
+                }
+            }
+        }
+    }
+    if votes != 0 {
+        return votes > 0;
+    }
+
+    // no signal from the data, so assume a header if the first row looks like column names
+    let mut names: Vec<&String> = first.iter().collect();
+    names.sort();
+    names.dedup();
+    names.len() == first.len()
+        && first
+            .iter()
+            .all(|v| !v.is_empty() && infer_type(v) == Some(DataType::Utf8))
+}
+
+fn infer_columns(rows: &[Vec<String>], has_header: bool) -> Vec<(String, DataType)> {
+    let num_columns = rows.first().map(|r| r.len()).unwrap_or(0);
+    let data = if has_header { &rows[1..] } else { rows };
+    (0..num_columns)
+        .map(|i| {
+            let name = match rows.first() {
+                Some(header) if has_header && !header[i].is_empty() => header[i].clone(),
+                _ => format!("column_{}", i + 1),
+            };
+            let values: Vec<&str> = data
+                .iter()
+                .filter_map(|r| r.get(i).map(|v| v.as_str()))
+                .collect();
+            (name, infer_column_type(&values))
+        })
+        .collect()
+}
+
+/// Infer the narrowest type that can represent every non-empty value in a column
+fn infer_column_type(values: &[&str]) -> DataType {
+    let mut column_type: Option<DataType> = None;
+    for value in values {
+        let Some(value_type) = infer_type(value) else {
+            continue;
+        };
+        column_type = Some(match (column_type, value_type) {
+            (None, t) => t,
+            (Some(a), b) if a == b => a,
+            (Some(DataType::Int64), DataType::Float64)
+            | (Some(DataType::Float64), DataType::Int64) => DataType::Float64,
+            (Some(DataType::Date32), t @ DataType::Timestamp(_, _))
+            | (Some(t @ DataType::Timestamp(_, _)), DataType::Date32) => t,
+            _ => return DataType::Utf8,
+        });
+    }
+    column_type.unwrap_or(DataType::Utf8)
+}
+
+/// Infer the type of a single value, returning `None` for empty values
+fn infer_type(value: &str) -> Option<DataType> {
+    let value = value.trim();
+    if value.is_empty() {
+        None
+    } else if value.eq_ignore_ascii_case("true") || value.eq_ignore_ascii_case("false") {
+        Some(DataType::Boolean)
+    } else if value.parse::<i64>().is_ok() {
+        Some(DataType::Int64)
+    } else if value.parse::<f64>().is_ok() && value.chars().any(|c| c.is_ascii_digit()) {
+        Some(DataType::Float64)
+    } else if is_date(value) {
+        Some(DataType::Date32)
+    } else if value.len() > 10
+        && value.is_char_boundary(10)
+        && is_date(&value[..10])
+        && is_time(&value[10..])
+    {
+        Some(DataType::Timestamp(TimeUnit::Nanosecond, None))
+    } else {
+        Some(DataType::Utf8)
+    }
+}
+
+/// Check for a date in `YYYY-MM-DD` format
+fn is_date(value: &str) -> bool {
+    let b = value.as_bytes();
+    b.len() == 10
+        && b.iter().enumerate().all(|(i, c)| match i {
+            4 | 7 => *c == b'-',
+            _ => c.is_ascii_digit(),
+        })
+}
+
+/// Check for a time in ` HH:MM:SS[.fff]` or `THH:MM:SS[.fff]` format
+fn is_time(value: &str) -> bool {
+    let b = value.as_bytes();
+    b.len() >= 9
+        && (b[0] == b' ' || b[0] == b'T')
+        && b[1..9].iter().enumerate().all(|(i, c)| match i {
+            2 | 5 => *c == b':',
+            _ => c.is_ascii_digit(),
+        })
+        && (b.len() == 9
+            || (b[9] == b'.' && b.len() > 10 && b[10..].iter().all(|c| c.is_ascii_digit())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn sniff(contents: &[u8]) -> CsvDialect {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("sample.csv");
+        std::fs::write(&path, contents).unwrap();
+        sniff_file(&path).unwrap()
+    }
+
+    #[test]
+    fn sniff_comma_separated_with_header() {
+        let dialect =
+            sniff(b"id,name,price,created\n1,apple,1.5,2024-01-02\n2,pear,2,2024-01-03\n");
+        assert_eq!(dialect.delimiter, b',');
+        assert_eq!(dialect.quote, b'"');
+        assert!(dialect.has_header);
+        assert_eq!(dialect.line_terminator, LineTerminator::Lf);
+        assert_eq!(dialect.encoding, CharacterEncoding::Utf8);
+        assert_eq!(
+            dialect.columns,
+            vec![
+                ("id".to_string(), DataType::Int64),
+                ("name".to_string(), DataType::Utf8),
+                ("price".to_string(), DataType::Float64),
+                ("created".to_string(), DataType::Date32),
+            ]
+        );
+        assert!(dialect.read_options().is_ok());
+    }
+
+    #[test]
+    fn sniff_semicolons_with_quoted_delimiters() {
+        let dialect = sniff(b"'a;b';2\r\n'c;d';3\r\n'e';4\r\n");
+        assert_eq!(dialect.delimiter, b';');
+        assert_eq!(dialect.quote, b'\'');
+        assert!(!dialect.has_header);
+        assert_eq!(dialect.line_terminator, LineTerminator::CrLf);
+        assert_eq!(dialect.columns[0].0, "column_1");
+        assert_eq!(dialect.columns[1].1, DataType::Int64);
+    }
+
+    #[test]
+    fn sniff_tabs_with_byte_order_mark() {
+        let dialect = sniff(b"\xEF\xBB\xBFname\tts\nx\t2024-01-02 03:04:05\n");
+        assert_eq!(dialect.delimiter, b'\t');
+        assert!(dialect.has_header);
+        assert_eq!(dialect.encoding, CharacterEncoding::Utf8Bom);
+        assert_eq!(
+            dialect.columns[1].1,
+            DataType::Timestamp(TimeUnit::Nanosecond, None)
+        );
+    }
+
+    #[test]
+    fn sniff_unsupported_dialects() {
+        let dialect = sniff(b"a|b\r1|2\r3|4\r");
+        assert_eq!(dialect.delimiter, b'|');
+        assert_eq!(dialect.line_terminator, LineTerminator::Cr);
+        assert!(dialect.read_options().is_err());
+
+        let dialect = sniff(b"caf\xE9,1\nna\xEFve,2\n");
+        assert_eq!(dialect.encoding, CharacterEncoding::Latin1);
+        assert!(dialect.read_options().is_err());
+    }
+
+    #[test]
+    fn sniff_empty_file() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("empty.csv");
+        std::fs::write(&path, "\n\n").unwrap();
+        assert!(sniff_file(&path).is_err());
+    }
+}
@@ -1,19 +1,26 @@
+use crate::csv::csv_error;
+use crate::json::{infer_json_records_schema, is_json_array, json_records_table};
+use crate::schema::{check_schema, load_schema};
+use crate::sniff::sniff_file;
 use crate::{Error, FileFormat};
 use datafusion::arrow::array;
-use datafusion::arrow::datatypes::DataType;
+use datafusion::arrow::datatypes::{DataType, Field, Schema};
 use datafusion::arrow::record_batch::RecordBatch;
 use datafusion::common::ScalarValue;
+use datafusion::logical_expr::Expr;
 use datafusion::prelude::{
-    AvroReadOptions, CsvReadOptions, DataFrame, NdJsonReadOptions, ParquetReadOptions,
-    SessionContext,
+    cast, ident, lit, AvroReadOptions, CsvReadOptions, DataFrame, NdJsonReadOptions,
+    ParquetReadOptions, SessionContext,
 };
-use std::path::Path;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 pub fn file_format(filename: &str) -> Result<FileFormat, Error> {
     match file_ending(filename)?.as_str() {
         "avro" => Ok(FileFormat::Avro),
         "csv" => Ok(FileFormat::Csv),
-        "json" => Ok(FileFormat::Json),
+        "json" | "jsonl" | "ndjson" => Ok(FileFormat::Json),
         "parquet" | "parq" => Ok(FileFormat::Parquet),
         other => Err(Error::General(format!(
             "unsupported file extension '{}'",
@@ -50,26 +57,287 @@ pub fn sanitize_table_name(name: &str) -> String {
     str
 }
 
+/// List the files that make up an input: the input itself when it is a file, or the files in a
+/// directory and its subdirectories that have the same extension as the directory, in name
+/// order. Hidden files, such as the temporary files of a write in progress, are skipped.
+pub fn list_input_files(path: &Path) -> Result<Vec<PathBuf>, Error> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    list_dir_files(path, path.extension())
+}
+
+/// List the files in a directory and its subdirectories in name order, keeping only files with
+/// the given extension when there is one. Hidden files and files starting with `_` are skipped.
+pub(crate) fn list_dir_files(dir: &Path, extension: Option<&OsStr>) -> Result<Vec<PathBuf>, Error> {
+    let mut files = vec![];
+    list_files(dir, extension, &mut files)?;
+    Ok(files)
+}
+
+fn list_files(
+    dir: &Path,
+    extension: Option<&OsStr>,
+    files: &mut Vec<PathBuf>,
+) -> Result<(), Error> {
+    let mut entries = std::fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    entries.sort();
+    for entry in entries {
+        let hidden = entry
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with(['.', '_']));
+        if hidden {
+            continue;
+        }
+        if entry.is_dir() {
+            list_files(&entry, extension, files)?;
+        } else if extension.is_none() || entry.extension() == extension {
+            files.push(entry);
+        }
+    }
+    Ok(())
+}
+
+/// Options that control how input files are read
+#[derive(Debug, Clone)]
+pub struct ReaderOptions {
+    /// Whether CSV files have a header row
+    pub has_header: bool,
+    /// Detect the CSV dialect by sampling the file instead of using the default dialect
+    pub auto_dialect: bool,
+    /// Dot-separated path to the records within each JSON document, such as `data.items`
+    pub json_path: Option<String>,
+    /// Explicit schema for CSV and JSON files, either a path to a JSON schema file or a list of
+    /// column definitions such as `id INT, name TEXT`
+    pub schema: Option<String>,
+    /// Fail if the file has columns that are missing from the schema, or lacks columns that are
+    /// declared in the schema, instead of dropping the extra columns and filling in the missing
+    /// ones with nulls
+    pub strict_schema: bool,
+}
+
+impl Default for ReaderOptions {
+    fn default() -> Self {
+        Self {
+            has_header: true,
+            auto_dialect: false,
+            json_path: None,
+            schema: None,
+            strict_schema: false,
+        }
+    }
+}
+
 pub async fn register_table(
     ctx: &SessionContext,
     table_name: &str,
     filename: &str,
+    options: &ReaderOptions,
 ) -> Result<DataFrame, Error> {
+    let schema = match &options.schema {
+        Some(schema) => Some(load_schema(ctx, schema).await?),
+        None => None,
+    };
+    match (&schema, file_format(filename)?) {
+        (Some(schema), FileFormat::Csv) => {
+            let df = read_csv_with_schema(ctx, filename, options, schema).await?;
+            ctx.register_table(table_name, df.into_view())?;
+        }
+        (Some(schema), FileFormat::Json) if options.strict_schema => {
+            let inferred = infer_json_records_schema(
+                &list_input_files(Path::new(filename))?,
+                options.json_path.as_deref(),
+            )?;
+            let columns: Vec<String> = inferred.fields().iter().map(|f| f.name().clone()).collect();
+            check_schema(schema, &columns)?;
+            register_file(ctx, table_name, filename, options, Some(schema)).await?;
+        }
+        _ => register_file(ctx, table_name, filename, options, schema.as_ref()).await?,
+    }
+    ctx.table(table_name).await.map_err(Error::from)
+}
+
+/// Read a CSV file with an explicit schema. Columns are matched to the schema by the names in
+/// the header row, or by position when the file has no header. Columns that are declared in the
+/// schema but missing from the file are filled with nulls, and columns that are not declared
+/// are dropped, unless the schema is strict.
+async fn read_csv_with_schema(
+    ctx: &SessionContext,
+    filename: &str,
+    options: &ReaderOptions,
+    schema: &Schema,
+) -> Result<DataFrame, Error> {
+    let file_extension = file_ending(filename)?;
+    let dialect = if options.auto_dialect {
+        Some(sniff_file(Path::new(filename))?)
+    } else {
+        None
+    };
+    let read_options = match &dialect {
+        Some(dialect) => dialect.read_options()?,
+        None => CsvReadOptions::new()
+            .has_header(options.has_header)
+            .file_extension(&file_extension),
+    };
+    let first_record = read_first_csv_record(filename, &read_options)?;
+    let columns: Vec<String> = if read_options.has_header {
+        first_record
+    } else {
+        (0..first_record.len())
+            .map(|i| match schema.fields().get(i) {
+                Some(field) => field.name().clone(),
+                None => format!("column_{}", i + 1),
+            })
+            .collect()
+    };
+    if options.strict_schema {
+        check_schema(schema, &columns)?;
+    }
+
+    let file_schema = Schema::new(
+        columns
+            .iter()
+            .map(|name| match schema.field_with_name(name) {
+                Ok(field) => field.clone(),
+                Err(_) => Field::new(name, DataType::Utf8, true),
+            })
+            .collect::<Vec<_>>(),
+    );
+    let df = ctx
+        .read_csv(
+            filename,
+            CsvReadOptions {
+                schema: Some(&file_schema),
+                ..read_options
+            },
+        )
+        .await?;
+    let projection = schema
+        .fields()
+        .iter()
+        .map(|field| {
+            if columns.contains(field.name()) {
+                ident(field.name())
+            } else {
+                cast(lit(ScalarValue::Null), field.data_type().clone()).alias(field.name())
+            }
+        })
+        .collect::<Vec<_>>();
+    Ok(df.select(projection)?)
+}
+
+/// Remove the byte order mark that is read as part of the first column name when a CSV file
+/// with a header row starts with one
+pub(crate) fn strip_byte_order_mark(df: DataFrame) -> Result<DataFrame, Error> {
+    let name = match df.schema().fields().first() {
+        Some(field) => match field.name().strip_prefix('\u{feff}') {
+            Some(name) => name.to_string(),
+            None => return Ok(df),
+        },
+        None => return Ok(df),
+    };
+    let exprs: Vec<Expr> = df
+        .schema()
+        .fields()
+        .iter()
+        .enumerate()
+        .map(|(i, field)| {
+            let expr = Expr::Column(field.qualified_column());
+            if i == 0 {
+                expr.alias(&name)
+            } else {
+                expr
+            }
+        })
+        .collect();
+    Ok(df.select(exprs)?)
+}
+
+/// Read the first record of a CSV file, or of the first CSV file in a directory, which holds the
+/// column names when the file has a header
+fn read_first_csv_record(
+    filename: &str,
+    read_options: &CsvReadOptions<'_>,
+) -> Result<Vec<String>, Error> {
+    let Some(file) = list_input_files(Path::new(filename))?.into_iter().next() else {
+        return Ok(vec![]);
+    };
+    let mut reader = ::csv::ReaderBuilder::new()
+        .has_headers(false)
+        .delimiter(read_options.delimiter)
+        .quote(read_options.quote)
+        .from_path(file)
+        .map_err(csv_error)?;
+    let mut record = ::csv::StringRecord::new();
+    reader.read_record(&mut record).map_err(csv_error)?;
+    Ok(record.iter().map(|name| name.to_string()).collect())
+}
+
+async fn register_file(
+    ctx: &SessionContext,
+    table_name: &str,
+    filename: &str,
+    options: &ReaderOptions,
+    schema: Option<&Schema>,
+) -> Result<(), Error> {
     match file_format(filename)? {
         FileFormat::Arrow => {
             unimplemented!()
         }
+        FileFormat::Avro | FileFormat::Parquet if schema.is_some() => {
+            return Err(Error::General(
+                "An explicit schema can only be used with CSV and JSON files".to_string(),
+            ))
+        }
         FileFormat::Avro => {
             ctx.register_avro(table_name, filename, AvroReadOptions::default())
                 .await?
         }
         FileFormat::Csv => {
-            ctx.register_csv(table_name, filename, CsvReadOptions::default())
-                .await?
+            let file_extension = file_ending(filename)?;
+            let dialect = if options.auto_dialect {
+                Some(sniff_file(Path::new(filename))?)
+            } else {
+                None
+            };
+            let read_options = match &dialect {
+                Some(dialect) => dialect.read_options()?,
+                None => CsvReadOptions::new()
+                    .has_header(options.has_header)
+                    .file_extension(&file_extension),
+            };
+            let df = ctx
+                .read_csv(
+                    filename,
+                    CsvReadOptions {
+                        schema,
+                        ..read_options
+                    },
+                )
+                .await?;
+            ctx.register_table(table_name, strip_byte_order_mark(df)?.into_view())?;
+        }
+        FileFormat::Json if options.json_path.is_some() || is_json_array(Path::new(filename))? => {
+            let table = json_records_table(
+                Path::new(filename),
+                options.json_path.as_deref(),
+                schema.map(|s| Arc::new(s.clone())),
+            )?;
+            ctx.register_table(table_name, Arc::new(table))?;
         }
         FileFormat::Json => {
-            ctx.register_json(table_name, filename, NdJsonReadOptions::default())
-                .await?
+            ctx.register_json(
+                table_name,
+                filename,
+                NdJsonReadOptions {
+                    schema,
+                    file_extension: &file_ending(filename)?,
+                    ..Default::default()
+                },
+            )
+            .await?
         }
         FileFormat::Parquet => {
             ctx.register_parquet(
@@ -83,7 +351,7 @@ pub async fn register_table(
             .await?
         }
     }
-    ctx.table(table_name).await.map_err(Error::from)
+    Ok(())
 }
 
 pub struct RowIter {
@@ -103,7 +371,7 @@ impl RowIter {
 }
 
 impl Iterator for RowIter {
-    type Item = Vec<ScalarValue>;
+    type Item = Result<Vec<ScalarValue>, Error>;
 
     fn next(&mut self) -> Option<Self::Item> {
         while self.current_batch < self.batches.len() {
@@ -190,14 +458,20 @@ impl Iterator for RowIter {
                                     array.as_any().downcast_ref::<array::Date64Array>().unwrap();
                                 row.push(ScalarValue::Date64(Some(array.value(row_index))));
                             }
-                            other => {
-                                println!("unsupported type: {}", other);
-                                todo!("unsupported data type")
-                            }
+                            // timestamps, decimals, nested types, etc.
+                            other => match ScalarValue::try_from_array(array, row_index) {
+                                Ok(value) => row.push(value),
+                                Err(e) => {
+                                    return Some(Err(Error::General(format!(
+                                        "Unsupported data type {}: {}",
+                                        other, e
+                                    ))))
+                                }
+                            },
                         }
                     }
                 }
-                return Some(row);
+                return Some(Ok(row));
             } else {
                 // move onto next batch
                 self.current_batch += 1;
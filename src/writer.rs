@@ -0,0 +1,582 @@
+use crate::avro::{batch_to_avro, write_avro_header};
+use crate::convert::ConvertOptions;
+use crate::csv::{CsvWriter, CsvWriterOptions};
+use crate::json::{JsonWriter, JsonWriterOptions};
+use crate::partition::group_rows;
+use crate::{Error, FileFormat};
+use apache_avro::{Codec, Schema as AvroSchema, Writer as AvroWriter};
+use datafusion::arrow::array::UInt32Array;
+use datafusion::arrow::compute::take;
+use datafusion::arrow::datatypes::SchemaRef;
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::parquet::arrow::ArrowWriter;
+use datafusion::parquet::file::properties::WriterProperties;
+use datafusion::prelude::DataFrame;
+use futures::StreamExt;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Number of rows to write between checks of the file size when splitting by size
+const SIZE_CHECK_ROWS: usize = 1024;
+
+/// Limits on the size of each output file. When a file reaches a limit, the output rolls over
+/// to a new numbered file.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FileSplitOptions {
+    /// Maximum number of rows in each file
+    pub max_rows_per_file: Option<usize>,
+    /// Approximate size of each file in bytes. Files are closed once they reach this size, so
+    /// they can exceed it slightly.
+    pub target_file_size: Option<usize>,
+}
+
+impl FileSplitOptions {
+    pub fn is_enabled(&self) -> bool {
+        self.max_rows_per_file.is_some() || self.target_file_size.is_some()
+    }
+}
+
+/// Parse a size in bytes with an optional binary unit, such as `512KB`, `128MB` or `1GB`
+pub fn parse_size(size: &str) -> Result<usize, Error> {
+    let upper = size.trim().to_uppercase();
+    let digits = upper.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let multiplier = match upper[digits.len()..]
+        .trim_end_matches("IB")
+        .trim_end_matches('B')
+    {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        _ => return Err(Error::General(format!("Invalid size '{}'", size))),
+    };
+    match digits.trim().parse::<usize>() {
+        Ok(n) if n > 0 => n
+            .checked_mul(multiplier)
+            .ok_or_else(|| Error::General(format!("Size '{}' is too large", size))),
+        _ => Err(Error::General(format!("Invalid size '{}'", size))),
+    }
+}
+
+/// Temporary outputs that are still being written, which are removed if the process is
+/// interrupted
+static PENDING_OUTPUTS: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// An output file or directory that is written to a hidden temporary path next to the target
+/// and renamed to the target once it is complete, so that readers never see partial output.
+/// The temporary output is removed if it is dropped without being committed.
+pub struct AtomicOutput {
+    target: PathBuf,
+    temp: PathBuf,
+}
+
+impl AtomicOutput {
+    /// Prepare to write to the target path, failing if it already exists unless `overwrite` is
+    /// set
+    pub fn create(target: &str, overwrite: bool) -> Result<Self, Error> {
+        let target = PathBuf::from(target);
+        if target.exists() && !overwrite {
+            return Err(Error::General(format!(
+                "Output path '{}' already exists, use --force to overwrite it",
+                target.display()
+            )));
+        }
+        let file_name = target
+            .file_name()
+            .ok_or_else(|| Error::General(format!("Invalid output path '{}'", target.display())))?
+            .to_string_lossy();
+        // the temporary name keeps the extension, which determines the output format
+        let temp = target.with_file_name(format!(".bdt-{}-{}", std::process::id(), file_name));
+        remove_path(&temp)?;
+        PENDING_OUTPUTS.lock().unwrap().push(temp.clone());
+        Ok(Self { target, temp })
+    }
+
+    /// The temporary path to write the output to
+    pub fn path(&self) -> &str {
+        // the temporary path is built from the target path, which is valid UTF-8
+        self.temp.to_str().unwrap()
+    }
+
+    /// Move the complete output into place, replacing any existing output
+    pub fn commit(self) -> Result<(), Error> {
+        // a file can be renamed over a file, but when a directory is involved on either side the
+        // existing output is moved aside first, so that it can be restored if the rename fails
+        let replaces_dir = self.target.is_dir() || (self.temp.is_dir() && self.target.exists());
+        if !replaces_dir {
+            std::fs::rename(&self.temp, &self.target)?;
+            return Ok(());
+        }
+        let name = format!(
+            ".bdt-{}-old-{}",
+            std::process::id(),
+            self.target.file_name().unwrap().to_string_lossy()
+        );
+        let old = self.target.with_file_name(name);
+        remove_path(&old)?;
+        std::fs::rename(&self.target, &old)?;
+        if let Err(e) = std::fs::rename(&self.temp, &self.target) {
+            let _ = std::fs::rename(&old, &self.target);
+            return Err(e.into());
+        }
+        remove_path(&old)
+    }
+}
+
+impl Drop for AtomicOutput {
+    fn drop(&mut self) {
+        PENDING_OUTPUTS.lock().unwrap().retain(|p| p != &self.temp);
+        // nothing remains to remove once the output has been committed
+        let _ = remove_path(&self.temp);
+    }
+}
+
+/// Remove the temporary outputs that are still being written, such as when the process is
+/// interrupted
+pub fn remove_pending_outputs() {
+    for path in PENDING_OUTPUTS.lock().unwrap().drain(..) {
+        let _ = remove_path(&path);
+    }
+}
+
+fn remove_path(path: &Path) -> Result<(), Error> {
+    if path.is_dir() {
+        std::fs::remove_dir_all(path)?;
+    } else if path.exists() {
+        std::fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+/// The output format and settings used to create writers for record batches with a given
+/// schema
+pub struct WriterConfig {
+    format: FileFormat,
+    schema: SchemaRef,
+    parquet: WriterProperties,
+    avro_schema: Option<AvroSchema>,
+    avro_codec: Codec,
+    csv: CsvWriterOptions,
+    json: JsonWriterOptions,
+}
+
+impl WriterConfig {
+    pub fn try_new(
+        format: FileFormat,
+        schema: SchemaRef,
+        options: &ConvertOptions,
+    ) -> Result<Self, Error> {
+        let (parquet, avro) = (&options.parquet, &options.avro);
+        let avro_schema = match format {
+            FileFormat::Avro => Some(avro.avro_schema(&schema)?),
+            FileFormat::Parquet => {
+                parquet.validate(&schema)?;
+                None
+            }
+            FileFormat::Csv | FileFormat::Json => None,
+            FileFormat::Arrow => {
+                return Err(Error::General(
+                    "Writing Arrow files is not supported".to_string(),
+                ))
+            }
+        };
+        // key-value metadata is also stored in the Arrow schema that is embedded in the footer
+        let schema = match format {
+            FileFormat::Parquet if !parquet.key_value_metadata.is_empty() => {
+                let mut metadata = schema.metadata().clone();
+                metadata.extend(parquet.key_value_metadata.iter().cloned());
+                Arc::new(schema.as_ref().clone().with_metadata(metadata))
+            }
+            _ => schema,
+        };
+        Ok(Self {
+            format,
+            parquet: parquet.writer_properties(&schema)?,
+            schema,
+            avro_schema,
+            avro_codec: avro.codec,
+            csv: options.csv.clone(),
+            json: options.json.clone(),
+        })
+    }
+
+    /// The schema of the record batches to write
+    pub fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    /// Create a new file and a writer for it
+    pub fn create(&self, path: &Path) -> Result<FileWriter<'_>, Error> {
+        let bytes_written = Arc::new(AtomicUsize::new(0));
+        let file = CountingWriter {
+            inner: File::create(path)?,
+            count: bytes_written.clone(),
+        };
+        let writer = match (&self.format, &self.avro_schema) {
+            (FileFormat::Avro, Some(schema)) => FormatWriter::Avro(
+                AvroWriter::with_codec(schema, BufWriter::new(file), self.avro_codec),
+                schema,
+                self.avro_codec,
+            ),
+            (FileFormat::Csv, _) => FormatWriter::Csv(Box::new(CsvWriter::try_new(
+                BufWriter::new(file),
+                &self.schema,
+                &self.csv,
+            )?)),
+            (FileFormat::Json, _) => FormatWriter::Json(Box::new(JsonWriter::try_new(
+                BufWriter::new(file),
+                &self.json,
+            )?)),
+            _ => FormatWriter::Parquet(ArrowWriter::try_new(
+                file,
+                self.schema.clone(),
+                Some(self.parquet.clone()),
+            )?),
+        };
+        Ok(FileWriter {
+            writer,
+            bytes_written,
+            rows_written: 0,
+        })
+    }
+}
+
+/// Counts the bytes written to the underlying writer
+struct CountingWriter<W> {
+    inner: W,
+    count: Arc<AtomicUsize>,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.count.fetch_add(n, Ordering::Relaxed);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+type OutputFile = CountingWriter<File>;
+
+enum FormatWriter<'a> {
+    Avro(AvroWriter<'a, BufWriter<OutputFile>>, &'a AvroSchema, Codec),
+    Csv(Box<CsvWriter<BufWriter<OutputFile>>>),
+    Json(Box<JsonWriter<BufWriter<OutputFile>>>),
+    Parquet(ArrowWriter<OutputFile>),
+}
+
+/// Writes record batches to a single file
+pub struct FileWriter<'a> {
+    writer: FormatWriter<'a>,
+    bytes_written: Arc<AtomicUsize>,
+    rows_written: usize,
+}
+
+impl FileWriter<'_> {
+    pub fn write(&mut self, batch: &RecordBatch) -> Result<(), Error> {
+        match &mut self.writer {
+            FormatWriter::Avro(writer, schema, _) => {
+                for record in batch_to_avro(batch)? {
+                    writer.append(record.resolve(schema)?)?;
+                }
+            }
+            FormatWriter::Csv(writer) => writer.write(batch)?,
+            FormatWriter::Json(writer) => writer.write(batch)?,
+            FormatWriter::Parquet(writer) => writer.write(batch)?,
+        }
+        self.rows_written += batch.num_rows();
+        Ok(())
+    }
+
+    pub fn rows_written(&self) -> usize {
+        self.rows_written
+    }
+
+    /// Approximate size of the file, including data that is buffered but not yet written
+    pub fn bytes_written(&self) -> usize {
+        let buffered = match &self.writer {
+            FormatWriter::Parquet(writer) => writer.in_progress_size(),
+            _ => 0,
+        };
+        self.bytes_written.load(Ordering::Relaxed) + buffered
+    }
+
+    /// Flush any buffered data and finish the file
+    pub fn close(self) -> Result<(), Error> {
+        match self.writer {
+            FormatWriter::Avro(writer, schema, codec) => {
+                let mut inner = writer.into_inner()?;
+                if self.rows_written == 0 {
+                    write_avro_header(&mut inner, schema, codec)?;
+                }
+                inner.into_inner().map_err(|e| e.into_error())?;
+            }
+            FormatWriter::Csv(writer) => {
+                writer
+                    .into_inner()?
+                    .into_inner()
+                    .map_err(|e| e.into_error())?;
+            }
+            FormatWriter::Json(writer) => {
+                writer.finish()?.into_inner().map_err(|e| e.into_error())?;
+            }
+            FormatWriter::Parquet(writer) => {
+                writer.close()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes record batches to numbered files such as `part-0.parquet` in a directory, rolling
+/// over to a new file when the current file reaches the size limits
+pub struct RollingWriter<'a> {
+    config: &'a WriterConfig,
+    dir: PathBuf,
+    extension: String,
+    split: FileSplitOptions,
+    next_part: usize,
+    current: Option<FileWriter<'a>>,
+}
+
+impl<'a> RollingWriter<'a> {
+    pub fn new(
+        config: &'a WriterConfig,
+        dir: PathBuf,
+        extension: &str,
+        split: FileSplitOptions,
+    ) -> Self {
+        Self {
+            config,
+            dir,
+            extension: extension.to_string(),
+            split,
+            next_part: 0,
+            current: None,
+        }
+    }
+
+    /// Whether a file is currently open
+    pub fn is_open(&self) -> bool {
+        self.current.is_some()
+    }
+
+    pub fn write(&mut self, batch: &RecordBatch) -> Result<(), Error> {
+        let mut offset = 0;
+        while offset < batch.num_rows() {
+            let writer = match &mut self.current {
+                Some(writer) => writer,
+                None => {
+                    std::fs::create_dir_all(&self.dir)?;
+                    let path = self
+                        .dir
+                        .join(format!("part-{}.{}", self.next_part, self.extension));
+                    self.next_part += 1;
+                    self.current.insert(self.config.create(&path)?)
+                }
+            };
+            let mut len = batch.num_rows() - offset;
+            if let Some(max_rows) = self.split.max_rows_per_file {
+                len = len.min(max_rows - writer.rows_written());
+            }
+            if self.split.target_file_size.is_some() {
+                len = len.min(SIZE_CHECK_ROWS);
+            }
+            writer.write(&batch.slice(offset, len))?;
+            offset += len;
+
+            let full_rows = self
+                .split
+                .max_rows_per_file
+                .is_some_and(|max_rows| writer.rows_written() >= max_rows);
+            let full_size = self
+                .split
+                .target_file_size
+                .is_some_and(|size| writer.bytes_written() >= size);
+            if full_rows || full_size {
+                self.close_file()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Close the current file. Later writes go to a new file.
+    pub fn close_file(&mut self) -> Result<(), Error> {
+        match self.current.take() {
+            Some(writer) => writer.close(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Write the results of a DataFrame to a single file
+pub async fn write_file(
+    df: DataFrame,
+    path: &str,
+    format: FileFormat,
+    options: &ConvertOptions,
+) -> Result<(), Error> {
+    // the schema of the stream can be more precise than the logical schema of the DataFrame
+    let mut stream = df.execute_stream().await?;
+    let config = WriterConfig::try_new(format, stream.schema(), options)?;
+    let mut writer = config.create(Path::new(path))?;
+    while let Some(batch) = stream.next().await {
+        let batch = batch?;
+        writer.write(&RecordBatch::try_new(
+            config.schema(),
+            batch.columns().to_vec(),
+        )?)?;
+        // the input stream rarely waits, so yield to let the runtime stop an interrupted write
+        tokio::task::yield_now().await;
+    }
+    writer.close()
+}
+
+/// Create a file without rows, which still has the header or schema of formats that have one
+pub fn write_empty_file(
+    path: &str,
+    format: FileFormat,
+    schema: SchemaRef,
+    options: &ConvertOptions,
+) -> Result<(), Error> {
+    let config = WriterConfig::try_new(format, schema, options)?;
+    config.create(Path::new(path))?.close()
+}
+
+/// Write the results of a DataFrame to a directory of numbered files, optionally partitioned
+/// into a Hive-style directory tree such as `region=EU/date=2024-01-01/part-0.parquet` and
+/// split into files of limited size
+pub async fn write_files(
+    df: DataFrame,
+    path: &str,
+    format: FileFormat,
+    options: &ConvertOptions,
+) -> Result<(), Error> {
+    let (partition, split) = (&options.partition, &options.split);
+    let mut stream = df.execute_stream().await?;
+    let schema = stream.schema();
+    let partition_indices = partition
+        .columns
+        .iter()
+        .map(|column| schema.index_of(column))
+        .collect::<Result<Vec<_>, _>>()?;
+    let data_indices: Vec<usize> = (0..schema.fields().len())
+        .filter(|i| !partition.exclude_columns || !partition_indices.contains(i))
+        .collect();
+    if data_indices.is_empty() {
+        return Err(Error::General(
+            "Cannot exclude partition columns because no other columns would remain".to_string(),
+        ));
+    }
+    if partition.max_open_files == Some(0) || split.max_rows_per_file == Some(0) {
+        return Err(Error::General("File limits must be at least 1".to_string()));
+    }
+    let config = WriterConfig::try_new(format, Arc::new(schema.project(&data_indices)?), options)?;
+    // files are only created once there are rows to write, so a result without rows is an
+    // empty directory
+    std::fs::create_dir_all(path)?;
+    let extension = match Path::new(path).extension() {
+        Some(ext) => ext.to_string_lossy().to_string(),
+        // a directory without an extension, such as the output of a rewrite
+        None => match format {
+            FileFormat::Arrow => "arrow",
+            FileFormat::Avro => "avro",
+            FileFormat::Csv => "csv",
+            FileFormat::Json => "json",
+            FileFormat::Parquet => "parquet",
+        }
+        .to_string(),
+    };
+
+    // writers for each partition directory, with a counter of when each was last used
+    let mut writers: HashMap<PathBuf, (RollingWriter, usize)> = HashMap::new();
+    let mut counter = 0;
+    while let Some(batch) = stream.next().await {
+        let batch = batch?;
+        for (dir, rows) in group_rows(&batch, &partition.columns, &partition_indices) {
+            let columns = if partition.columns.is_empty() {
+                data_indices
+                    .iter()
+                    .map(|i| batch.column(*i).clone())
+                    .collect()
+            } else {
+                let indices = UInt32Array::from(rows);
+                data_indices
+                    .iter()
+                    .map(|i| take(batch.column(*i), &indices, None))
+                    .collect::<Result<Vec<_>, _>>()?
+            };
+            let data = RecordBatch::try_new(config.schema(), columns)?;
+
+            counter += 1;
+            let is_open = writers.get(&dir).is_some_and(|(w, _)| w.is_open());
+            if !is_open {
+                if let Some(max) = partition.max_open_files {
+                    let open = writers.values().filter(|(w, _)| w.is_open()).count();
+                    if open >= max {
+                        close_least_recently_used(&mut writers)?;
+                    }
+                }
+            }
+            let (writer, last_used) = writers.entry(dir.clone()).or_insert_with(|| {
+                let dir = Path::new(path).join(&dir);
+                (RollingWriter::new(&config, dir, &extension, *split), 0)
+            });
+            *last_used = counter;
+            writer.write(&data)?;
+        }
+        tokio::task::yield_now().await;
+    }
+    for (mut writer, _) in writers.into_values() {
+        writer.close_file()?;
+    }
+    Ok(())
+}
+
+fn close_least_recently_used(
+    writers: &mut HashMap<PathBuf, (RollingWriter, usize)>,
+) -> Result<(), Error> {
+    let oldest = writers
+        .values_mut()
+        .filter(|(w, _)| w.is_open())
+        .min_by_key(|(_, last_used)| *last_used);
+    match oldest {
+        Some((writer, _)) => writer.close_file(),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_size_units() {
+        assert_eq!(parse_size("100").unwrap(), 100);
+        assert_eq!(parse_size("512KB").unwrap(), 512 << 10);
+        assert_eq!(parse_size("128mb").unwrap(), 128 << 20);
+        assert_eq!(parse_size(" 1 GiB ").unwrap(), 1 << 30);
+        assert_eq!(parse_size("2T").unwrap(), 2 << 40);
+    }
+
+    #[test]
+    fn parse_size_rejects_invalid_sizes() {
+        for size in ["", "0", "-1MB", "1.5GB", "10PB", "MB"] {
+            assert!(parse_size(size).is_err(), "{} was accepted", size);
+        }
+    }
+
+    #[test]
+    fn parse_size_rejects_overflow() {
+        assert!(parse_size(&format!("{}TB", usize::MAX >> 39)).is_err());
+        assert!(parse_size(&format!("{}B", usize::MAX)).is_ok());
+    }
+}
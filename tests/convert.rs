@@ -0,0 +1,93 @@
+use bdt::convert::{convert_files, ConvertOptions};
+use bdt::partition::PartitionOptions;
+use bdt::utils::{register_table, ReaderOptions};
+use bdt::writer::FileSplitOptions;
+use datafusion::prelude::SessionContext;
+use std::path::Path;
+use tempfile::TempDir;
+
+fn path(dir: &TempDir, name: &str) -> String {
+    dir.path().join(name).to_str().unwrap().to_string()
+}
+
+async fn convert(input: &str, output: &str, options: ConvertOptions) {
+    let options = ConvertOptions {
+        quiet: true,
+        ..options
+    };
+    convert_files(&SessionContext::new(), input, output, &options)
+        .await
+        .unwrap();
+}
+
+/// Read back a converted file and return its column names and number of rows
+async fn read_output(path: &str) -> (Vec<String>, usize) {
+    let ctx = SessionContext::new();
+    let df = register_table(&ctx, "t", path, &ReaderOptions::default())
+        .await
+        .unwrap();
+    let columns = df
+        .schema()
+        .fields()
+        .iter()
+        .map(|f| f.name().clone())
+        .collect();
+    (columns, df.count().await.unwrap())
+}
+
+#[tokio::test]
+async fn convert_empty_input_to_single_files() {
+    let dir = TempDir::new().unwrap();
+    let input = path(&dir, "empty.csv");
+    std::fs::write(&input, "a,b\n").unwrap();
+    for name in ["empty.parquet", "empty_out.csv", "empty.json", "empty.avro"] {
+        let output = path(&dir, name);
+        let options = ConvertOptions {
+            single_file: true,
+            ..Default::default()
+        };
+        convert(&input, &output, options).await;
+        assert!(Path::new(&output).is_file(), "{} was not written", name);
+    }
+    // formats with a header or schema keep the columns
+    for name in ["empty.parquet", "empty_out.csv", "empty.avro"] {
+        let (columns, rows) = read_output(&path(&dir, name)).await;
+        assert_eq!(columns, vec!["a", "b"]);
+        assert_eq!(rows, 0);
+    }
+}
+
+#[tokio::test]
+async fn convert_empty_input_to_directories() {
+    let dir = TempDir::new().unwrap();
+    let input = path(&dir, "empty.csv");
+    std::fs::write(&input, "a,b\n").unwrap();
+    let outputs = [
+        ("plain.parquet", ConvertOptions::default()),
+        (
+            "partitioned.parquet",
+            ConvertOptions {
+                partition: PartitionOptions {
+                    columns: vec!["a".to_string()],
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        ),
+        (
+            "split.csv",
+            ConvertOptions {
+                split: FileSplitOptions {
+                    max_rows_per_file: Some(10),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        ),
+    ];
+    for (name, options) in outputs {
+        let output = path(&dir, name);
+        convert(&input, &output, options).await;
+        assert!(Path::new(&output).is_dir(), "{} was not written", name);
+    }
+}
@@ -0,0 +1,65 @@
+use bdt::utils::{register_table, ReaderOptions};
+use datafusion::arrow::util::pretty::pretty_format_batches;
+use datafusion::prelude::SessionContext;
+use tempfile::TempDir;
+
+async fn read(path: &str, options: &ReaderOptions) -> String {
+    let ctx = SessionContext::new();
+    let df = register_table(&ctx, "t", path, options).await.unwrap();
+    let batches = df.collect().await.unwrap();
+    pretty_format_batches(&batches).unwrap().to_string()
+}
+
+#[tokio::test]
+async fn read_decimals_from_json_array_with_schema() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("prices.json");
+    std::fs::write(
+        &path,
+        r#"[{"id": 1, "price": 1.25}, null, {"id": 2, "price": 10.5}]"#,
+    )
+    .unwrap();
+    let options = ReaderOptions {
+        schema: Some("id INT, price DECIMAL(10, 2)".to_string()),
+        ..Default::default()
+    };
+    let expected = [
+        "+----+-------+",
+        "| id | price |",
+        "+----+-------+",
+        "| 1  | 1.25  |",
+        "| 2  | 10.50 |",
+        "+----+-------+",
+    ];
+    assert_eq!(
+        read(path.to_str().unwrap(), &options).await,
+        expected.join("\n")
+    );
+}
+
+#[tokio::test]
+async fn read_decimals_at_json_path_with_schema() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("prices.json");
+    std::fs::write(
+        &path,
+        r#"{"data": {"items": [{"id": 1, "price": 99999999.99}]}}"#,
+    )
+    .unwrap();
+    let options = ReaderOptions {
+        json_path: Some("data.items".to_string()),
+        schema: Some("id INT, price DECIMAL(10, 2)".to_string()),
+        ..Default::default()
+    };
+    let expected = [
+        "+----+-------------+",
+        "| id | price       |",
+        "+----+-------------+",
+        "| 1  | 99999999.99 |",
+        "+----+-------------+",
+    ];
+    assert_eq!(
+        read(path.to_str().unwrap(), &options).await,
+        expected.join("\n")
+    );
+}
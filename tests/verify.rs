@@ -0,0 +1,89 @@
+use bdt::convert::{convert_files, ConvertOptions};
+use bdt::json::{DecimalFormat, JsonWriterOptions, TimestampFormat};
+use bdt::Error;
+use datafusion::arrow::datatypes::TimeUnit;
+use datafusion::prelude::SessionContext;
+use tempfile::TempDir;
+
+const QUERY: &str = "SELECT id, CAST(price AS DECIMAL(10, 2)) AS price, \
+    CAST(ts AS TIMESTAMP) AS ts FROM t";
+
+/// Convert a CSV file with decimals and timestamps with verification enabled
+async fn convert_verified(
+    dir: &TempDir,
+    timestamp: &str,
+    output: &str,
+    options: ConvertOptions,
+) -> Result<(), Error> {
+    let input = dir.path().join("input.csv");
+    std::fs::write(
+        &input,
+        format!(
+            "id,price,ts\n1,1.25,{}\n2,,2024-05-06T07:08:09\n",
+            timestamp
+        ),
+    )
+    .unwrap();
+    let output = dir.path().join(output);
+    let options = ConvertOptions {
+        sql: Some(QUERY.to_string()),
+        single_file: true,
+        verify: true,
+        quiet: true,
+        ..options
+    };
+    convert_files(
+        &SessionContext::new(),
+        input.to_str().unwrap(),
+        output.to_str().unwrap(),
+        &options,
+    )
+    .await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn verify_decimals_and_timestamps() {
+    let dir = TempDir::new().unwrap();
+    for output in ["out.parquet", "out.json", "out.avro", "out.csv"] {
+        convert_verified(
+            &dir,
+            "2024-01-02T03:04:05.123456",
+            output,
+            ConvertOptions::default(),
+        )
+        .await
+        .unwrap_or_else(|e| panic!("{} failed verification: {}", output, e));
+    }
+}
+
+#[tokio::test]
+async fn verify_json_with_decimal_strings_and_epoch_timestamps() {
+    let dir = TempDir::new().unwrap();
+    let options = ConvertOptions {
+        json: JsonWriterOptions {
+            decimal: DecimalFormat::String,
+            timestamp: TimestampFormat::Epoch(TimeUnit::Millisecond),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    convert_verified(&dir, "2024-01-02T03:04:05.123", "out.json", options)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn verify_rejects_lost_values() {
+    let dir = TempDir::new().unwrap();
+    // Avro stores timestamps in microseconds at most
+    let result = convert_verified(
+        &dir,
+        "2024-01-02T03:04:05.123456789",
+        "out.avro",
+        ConvertOptions::default(),
+    )
+    .await;
+    assert!(result.is_err());
+    assert!(!dir.path().join("out.avro").exists());
+}